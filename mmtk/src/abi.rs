@@ -1,11 +1,12 @@
 use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
 
 use libc::size_t;
 use mmtk::util::{ObjectReference, Address, VMWorkerThread};
 use crate::{UPCALLS, ScalaNative, object_scanning::LAST_FIELD_OFFSET};
 use mmtk::scheduler::{GCController, GCWorker};
 use crate::collection::{GC_THREAD_KIND_CONTROLLER, GC_THREAD_KIND_WORKER};
-use crate::scanning::{ALLOCATION_ALIGNMENT_LAZY, is_ptr_aligned, align_ptr};
+use crate::scanning::{ALLOCATION_ALIGNMENT_LAZY, is_ptr_aligned, align_ptr, WeakRefBuffer};
 
 #[cfg(feature = "scalanative_multithreading_enabled")]
 pub const MONITOR_INFLATION_MARK_MASK: word_t = 1;
@@ -87,6 +88,78 @@ pub struct ArrayHeader {
 	pub stride: i32,
 }
 
+/// A heap-allocated fragment of a captured stack, as used by delimited continuations.
+/// `size` is the number of bytes of stack words stored in `data`, which are scanned
+/// conservatively. The runtime copies the fragment back onto a thread stack when the
+/// continuation is resumed, so the chunk itself may be moved by the GC.
+#[repr(C)]
+pub struct StackChunk {
+	pub rtti: *mut Rtti,
+	#[cfg(feature = "uses_lockword")]
+	pub lock_word: *mut word_t,
+	pub size: usize,
+	pub data: [word_t; 0],
+}
+
+/// The rtti id range of stack chunk classes, registered through `mmtk_register_stack_chunk_ids`.
+/// The range is empty until the runtime registers it.
+static STACK_CHUNK_IDS_MIN: AtomicI32 = AtomicI32::new(1);
+static STACK_CHUNK_IDS_MAX: AtomicI32 = AtomicI32::new(0);
+/// Whether stack chunks are pinned when they are reached during a GC. Requires `object_pinning`.
+pub static PIN_STACK_CHUNKS: AtomicBool = AtomicBool::new(false);
+
+pub fn register_stack_chunk_ids(min: i32, max: i32) {
+	STACK_CHUNK_IDS_MIN.store(min, Ordering::SeqCst);
+	STACK_CHUNK_IDS_MAX.store(max, Ordering::SeqCst);
+}
+
+fn is_stack_chunk_id(id: i32) -> bool {
+	STACK_CHUNK_IDS_MIN.load(Ordering::Relaxed) <= id && id <= STACK_CHUNK_IDS_MAX.load(Ordering::Relaxed)
+}
+
+/// The rtti id range of ephemeron classes and the offsets of their key and value fields,
+/// registered through `mmtk_register_ephemerons`. The value of an ephemeron is only
+/// reachable through it if its key is reachable.
+static EPHEMERON_IDS_MIN: AtomicI32 = AtomicI32::new(1);
+static EPHEMERON_IDS_MAX: AtomicI32 = AtomicI32::new(0);
+static EPHEMERON_KEY_OFFSET: AtomicI32 = AtomicI32::new(-1);
+static EPHEMERON_VALUE_OFFSET: AtomicI32 = AtomicI32::new(-1);
+
+pub fn register_ephemerons(min: i32, max: i32, key_offset: i32, value_offset: i32) {
+	EPHEMERON_KEY_OFFSET.store(key_offset, Ordering::SeqCst);
+	EPHEMERON_VALUE_OFFSET.store(value_offset, Ordering::SeqCst);
+	EPHEMERON_IDS_MIN.store(min, Ordering::SeqCst);
+	EPHEMERON_IDS_MAX.store(max, Ordering::SeqCst);
+}
+
+/// The index of the timestamp field of soft references, registered through
+/// `mmtk_register_soft_ref_timestamp`. See `SoftReferences`.
+static SOFT_REF_TIMESTAMP_OFFSET: AtomicI32 = AtomicI32::new(-1);
+
+pub fn register_soft_ref_timestamp(offset: i32) {
+	SOFT_REF_TIMESTAMP_OFFSET.store(offset, Ordering::SeqCst);
+}
+
+/// The fields of an object that are only weakly traced: the referent of a weak reference,
+/// or the key and value of an ephemeron.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WeakFields {
+	None,
+	Referent(i32),
+	Ephemeron { key: i32, value: i32 },
+}
+
+impl WeakFields {
+	#[inline]
+	pub fn contains(self, field_offset: i32) -> bool {
+		match self {
+			WeakFields::None => false,
+			WeakFields::Referent(referent) => field_offset == referent,
+			WeakFields::Ephemeron { key, value } => field_offset == key || field_offset == value,
+		}
+	}
+}
+
 #[repr(C)]
 pub struct Chunk {
 	pub nothing: *mut libc::c_void,
@@ -116,6 +189,8 @@ impl Object {
 	pub fn size(&self) -> size_t {
 		if self.is_array() {
 			unsafe { self.as_array_object().size() }
+		} else if self.is_stack_chunk() {
+			unsafe { self.as_stack_chunk().size_in_heap() }
 		} else {
 				round_to_next_multiple((unsafe { &*self.rtti }).size as size_t, *ALLOCATION_ALIGNMENT_LAZY)
 		}
@@ -125,6 +200,8 @@ impl Object {
 		let rtti = align_ptr(self.rtti as *mut usize) as *mut Rtti;
 		if self.is_array_for_copy() {
 			unsafe { self.as_array_object().size() }
+		} else if is_stack_chunk_id(unsafe { (*rtti).rt.id }) {
+			unsafe { self.as_stack_chunk().size_in_heap() }
 		} else {
 			round_to_next_multiple((unsafe { &*rtti }).size as size_t, *ALLOCATION_ALIGNMENT_LAZY)
 		}
@@ -141,10 +218,51 @@ impl Object {
 		self.is_weak_reference() && field_offset == *WEAK_REF_FIELD_OFFSET
 	}
 
+	/// The fields of this object that are only weakly traced, read from its rtti once.
+	pub fn weak_fields(&self) -> WeakFields {
+		let id = unsafe { (*self.rtti).rt.id };
+		if *WEAK_REF_IDS_MIN <= id && id <= *WEAK_REF_IDS_MAX {
+			WeakFields::Referent(*WEAK_REF_FIELD_OFFSET)
+		} else if EPHEMERON_IDS_MIN.load(Ordering::Relaxed) <= id && id <= EPHEMERON_IDS_MAX.load(Ordering::Relaxed) {
+			WeakFields::Ephemeron {
+				key: EPHEMERON_KEY_OFFSET.load(Ordering::Relaxed),
+				value: EPHEMERON_VALUE_OFFSET.load(Ordering::Relaxed),
+			}
+		} else {
+			WeakFields::None
+		}
+	}
+
+	pub fn ephemeron_key_slot(&self) -> *mut Field_t {
+		unsafe { self.get_fields().offset(EPHEMERON_KEY_OFFSET.load(Ordering::Relaxed) as isize) }
+	}
+
+	pub fn ephemeron_value_slot(&self) -> *mut Field_t {
+		unsafe { self.get_fields().offset(EPHEMERON_VALUE_OFFSET.load(Ordering::Relaxed) as isize) }
+	}
+
+	/// The GC epoch during which this soft reference was last accessed, or `None` if the
+	/// runtime did not register the timestamp field.
+	pub fn soft_ref_timestamp(&self) -> Option<&AtomicU64> {
+		let offset = SOFT_REF_TIMESTAMP_OFFSET.load(Ordering::Relaxed);
+		if offset < 0 {
+			return None;
+		}
+		Some(unsafe { &*(self.get_fields().offset(offset as isize) as *const AtomicU64) })
+	}
+
 	pub unsafe fn as_array_object(&self) -> &ArrayHeader {
 		&*(self as *const _ as *const ArrayHeader)
 	}
 
+	pub fn is_stack_chunk(&self) -> bool {
+		is_stack_chunk_id(unsafe { (*self.rtti).rt.id })
+	}
+
+	pub unsafe fn as_stack_chunk(&self) -> &StackChunk {
+		&*(self as *const _ as *const StackChunk)
+	}
+
 	pub fn get_fields(&self) -> *mut Field_t {
 		let fields = self as *const _ as usize + mem::size_of_val(&self.rtti);
 		#[cfg(feature = "uses_lockword")]
@@ -188,6 +306,17 @@ impl ArrayHeader {
 	}
 }
 
+impl StackChunk {
+	pub fn size_in_heap(&self) -> size_t {
+		round_to_next_multiple(mem::size_of::<StackChunk>() + self.size, *ALLOCATION_ALIGNMENT_LAZY)
+	}
+
+	/// The stack words held by this chunk.
+	pub fn words(&self) -> &[word_t] {
+		unsafe { std::slice::from_raw_parts(self.data.as_ptr(), self.size / mem::size_of::<word_t>()) }
+	}
+}
+
 // If the lowest bit is 1, the lock is inflated
 #[cfg(feature = "uses_lockword")]
 pub fn field_is_inflated_lock(field: Field_t) -> bool {
@@ -226,6 +355,8 @@ impl From<&Object> for ObjectReference {
 pub struct GCThreadTLS {
     pub kind: libc::c_int,
     pub gc_context: *mut libc::c_void,
+    /// Not visible to C: the weak references reached by this worker.
+    pub weak_refs: WeakRefBuffer,
 }
 
 impl GCThreadTLS {
@@ -233,6 +364,7 @@ impl GCThreadTLS {
 			Self {
 					kind,
 					gc_context,
+					weak_refs: WeakRefBuffer::new(),
 			}
 	}
 
@@ -295,9 +427,3 @@ impl GCThreadTLS {
 			unsafe { &mut *(self.gc_context as *mut GCWorker<ScalaNative>) }
 	}
 }
-
-#[repr(C)]
-pub struct MutatorThreadNode {
-    pub value: *mut libc::c_void,
-    pub next: *mut MutatorThreadNode,
-}
@@ -1,30 +1,31 @@
+#[cfg(feature = "scalanative_multithreading_enabled")]
 use std::collections::VecDeque;
+#[cfg(feature = "scalanative_multithreading_enabled")]
 use std::marker::PhantomData;
 
 use mmtk::vm::ActivePlan;
 use mmtk::util::opaque_pointer::*;
 use mmtk::Mutator;
-use crate::MutatorClosure;
+#[cfg(feature = "scalanative_multithreading_enabled")]
+use crate::mutators::MUTATORS;
+#[cfg(not(feature = "scalanative_multithreading_enabled"))]
+use crate::mutators::SINGLE_MUTATOR;
+#[cfg(not(feature = "scalanative_multithreading_enabled"))]
+use mmtk::MutatorContext;
+#[cfg(not(feature = "scalanative_multithreading_enabled"))]
+use std::sync::atomic::Ordering;
 use crate::ScalaNative;
-use crate::UPCALLS;
 
+#[cfg(feature = "scalanative_multithreading_enabled")]
 struct ScalaNativeMutatorIterator<'a> {
     mutators: VecDeque<&'a mut Mutator<ScalaNative>>,
     phantom_data: PhantomData<&'a ()>,
 }
 
+#[cfg(feature = "scalanative_multithreading_enabled")]
 impl<'a> ScalaNativeMutatorIterator<'a> {
     fn new() -> Self {
-        let mut mutators = VecDeque::new();
-        unsafe {
-            // println!("number of mutators: {}", ((*UPCALLS).number_of_mutators)());
-            // print!("mutators: ");
-            ((*UPCALLS).get_mutators)(MutatorClosure::from_rust_closure(&mut |mutator| {
-                // print!("{:p}, ", mutator);
-                mutators.push_back(mutator);
-            }));
-            // println!();
-        }
+        let mutators = MUTATORS.entries().into_iter().map(|entry| unsafe { &mut *entry.mutator }).collect();
         Self {
             mutators,
             phantom_data: PhantomData,
@@ -32,6 +33,7 @@ impl<'a> ScalaNativeMutatorIterator<'a> {
     }
 }
 
+#[cfg(feature = "scalanative_multithreading_enabled")]
 impl<'a> Iterator for ScalaNativeMutatorIterator<'a> {
     type Item = &'a mut Mutator<ScalaNative>;
 
@@ -42,20 +44,20 @@ impl<'a> Iterator for ScalaNativeMutatorIterator<'a> {
 
 pub struct VMActivePlan<> {}
 
+#[cfg(feature = "scalanative_multithreading_enabled")]
 impl ActivePlan<ScalaNative> for VMActivePlan {
     fn number_of_mutators() -> usize {
-        unsafe { ((*UPCALLS).number_of_mutators)() }
+        MUTATORS.len()
     }
 
-    fn is_mutator(_tls: VMThread) -> bool {
-        unsafe { ((*UPCALLS).is_mutator)(_tls) }
+    fn is_mutator(tls: VMThread) -> bool {
+        MUTATORS.contains(VMMutatorThread(tls))
     }
 
-    fn mutator(_tls: VMMutatorThread) -> &'static mut Mutator<ScalaNative> {
-        println!("get mutator, tls = {:?}", _tls.0.0);
-        unsafe {
-            let m = ((*UPCALLS).get_mmtk_mutator)(_tls);
-            &mut *m
+    fn mutator(tls: VMMutatorThread) -> &'static mut Mutator<ScalaNative> {
+        match MUTATORS.mutator(tls) {
+            Some(mutator) => unsafe { &mut *mutator },
+            None => panic!("{:?} is not a mutator", tls),
         }
     }
 
@@ -72,3 +74,27 @@ impl ActivePlan<ScalaNative> for VMActivePlan {
     // }
 
 }
+
+/// A single-threaded runtime binds one mutator, so there is no registry to lock.
+#[cfg(not(feature = "scalanative_multithreading_enabled"))]
+impl ActivePlan<ScalaNative> for VMActivePlan {
+    fn number_of_mutators() -> usize {
+        (!SINGLE_MUTATOR.load(Ordering::Relaxed).is_null()) as usize
+    }
+
+    fn is_mutator(tls: VMThread) -> bool {
+        let mutator = SINGLE_MUTATOR.load(Ordering::Relaxed);
+        !mutator.is_null() && unsafe { (*mutator).get_tls() }.0 == tls
+    }
+
+    fn mutator(tls: VMMutatorThread) -> &'static mut Mutator<ScalaNative> {
+        let mutator = SINGLE_MUTATOR.load(Ordering::Relaxed);
+        debug_assert!(Self::is_mutator(tls.0), "{:?} is not the mutator", tls);
+        unsafe { &mut *mutator }
+    }
+
+    fn mutators<'a>() -> Box<dyn Iterator<Item = &'a mut Mutator<ScalaNative>> + 'a> {
+        let mutator = SINGLE_MUTATOR.load(Ordering::Relaxed);
+        Box::new(unsafe { mutator.as_mut() }.into_iter())
+    }
+}
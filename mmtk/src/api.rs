@@ -3,8 +3,10 @@
 
 use libc::c_char;
 use libc::c_void;
+#[cfg(feature = "object_pinning")]
+use libc::size_t;
+#[cfg(feature = "scalanative_multithreading_enabled")]
 use log::debug;
-use log::warn;
 use mmtk::memory_manager::is_mmtk_object;
 use mmtk::util::alloc::AllocatorInfo;
 use mmtk::util::alloc::AllocatorSelector;
@@ -14,28 +16,44 @@ use mmtk::util::options::PlanSelector;
 use mmtk::vm::EdgeVisitor;
 use mmtk::vm::edge_shape::SimpleEdge;
 use core::panic;
-use std::sync::Mutex;
 use std::sync::atomic::Ordering;
 use std::ffi::CStr;
-use std::sync::mpsc;
+use std::sync::Mutex;
+#[cfg(feature = "scalanative_multithreading_enabled")]
 use std::thread;
+use std::thread::JoinHandle;
 use mmtk::memory_manager;
 use mmtk::AllocationSemantics;
 use mmtk::util::{ObjectReference, Address};
 use mmtk::util::opaque_pointer::*;
 use mmtk::scheduler::{GCController, GCWorker};
 use mmtk::Mutator;
+use mmtk::MutatorContext;
 use crate::MutatorClosure;
+use crate::RangesClosure;
 use crate::ScalaNative;
 use crate::SINGLETON;
 use crate::BUILDER;
 use crate::ScalaNativeUpcalls;
 use crate::UPCALLS;
 use crate::abi::Object;
+#[cfg(feature = "object_pinning")]
+use crate::abi::word_t;
 use crate::binding::ScalaNativeBinding;
+use crate::roots::ROOT_RANGES;
+use crate::handles::{HandleTable, HANDLES};
+use crate::soft_refs::SOFT_REFERENCES;
+use crate::collection::GC_IN_FLIGHT;
+use crate::safepoint::{SafepointStats, SAFEPOINT, SAFEPOINT_TIMEOUT_MS};
+use crate::mutators::{StackBounds, MUTATORS};
+use crate::reference_handler::REFERENCE_HANDLER;
+use crate::gc_threads::{gc_nprocs, parse_cpu_list, GC_THREAD_OPTIONS};
+#[cfg(feature = "object_pinning")]
+use crate::pin_scopes::PIN_SCOPES;
 use crate::edges::ScalaNativeEdge;
 use crate::object_scanning::ClosureWrapper;
 use crate::scanning::HANDLER_FN;
+use crate::scanning::REFERENCE_QUEUE_HANDLER;
 
 #[no_mangle]
 pub extern "C" fn mmtk_init(min_heap_size: usize, max_heap_size: usize) {
@@ -50,6 +68,17 @@ pub extern "C" fn mmtk_init(min_heap_size: usize, max_heap_size: usize) {
         builder.options.plan.set(PlanSelector::Immix);
         let success = builder.options.gc_trigger.set(policy);
         debug_assert!(success, "Failed to set min heap size to {} and max heap size to {}", min_heap_size, max_heap_size);
+        // Parallel GC is opt-in for single-threaded programs. Running the GC on the allocating
+        // thread is not supported: mmtk-core 0.21 runs it on a controller and at least one
+        // worker, while the mutator waits in `block_for_gc`.
+        #[cfg(not(feature = "scalanative_multithreading_enabled"))]
+        let threads = gc_nprocs().or(Some(1));
+        #[cfg(feature = "scalanative_multithreading_enabled")]
+        let threads = gc_nprocs();
+        if let Some(threads) = threads {
+            let success = builder.options.threads.set(threads);
+            debug_assert!(success, "Failed to set the number of GC threads to {}", threads);
+        }
     }
 
     // Make sure MMTk has not yet been initialized
@@ -63,12 +92,73 @@ pub extern "C" fn mmtk_get_bytes_in_page() -> usize {
     constants::BYTES_IN_PAGE
 }
 
+/// Set the CPUs the GC threads run on, as a list such as "0-3,6". Each worker is pinned to one
+/// CPU of the list, round robin. Must be called before `initialize_collection`.
+/// Returns false if the list cannot be parsed.
+#[no_mangle]
+pub extern "C" fn mmtk_set_gc_thread_affinity(cpus: *const c_char) -> bool {
+    let cpus = unsafe { CStr::from_ptr(cpus) };
+    match cpus.to_str().ok().and_then(parse_cpu_list) {
+        Some(cpus) => {
+            GC_THREAD_OPTIONS.lock().unwrap().cpus = cpus;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Set the scheduling policy (e.g. `SCHED_FIFO`) and priority of the GC threads.
+/// Must be called before `initialize_collection`.
+#[no_mangle]
+pub extern "C" fn mmtk_set_gc_thread_policy(policy: libc::c_int, priority: libc::c_int) {
+    GC_THREAD_OPTIONS.lock().unwrap().policy = Some((policy, priority));
+}
+
+/// Pin the object at `addr`, so the GC never moves it, until `mmtk_unpin_object` is called.
+/// Returns false if it was already pinned.
 #[cfg(feature = "object_pinning")]
 #[no_mangle]
 pub extern "C" fn mmtk_pin_object(addr: *mut word_t) -> bool {
-    memory_manager::pin_object::<ScalaNative>(unsafe { ObjectReference::from_raw_address(Address::from_mut_ptr(addr)) })
+    memory_manager::pin_object::<ScalaNative>(ObjectReference::from_raw_address(Address::from_mut_ptr(addr)))
+}
+
+/// Unpin the object at `addr`. Returns false if it was not pinned.
+#[cfg(feature = "object_pinning")]
+#[no_mangle]
+pub extern "C" fn mmtk_unpin_object(addr: *mut word_t) -> bool {
+    memory_manager::unpin_object::<ScalaNative>(ObjectReference::from_raw_address(Address::from_mut_ptr(addr)))
+}
+
+#[cfg(feature = "object_pinning")]
+#[no_mangle]
+pub extern "C" fn mmtk_is_pinned(addr: *mut word_t) -> bool {
+    memory_manager::is_pinned::<ScalaNative>(ObjectReference::from_raw_address(Address::from_mut_ptr(addr)))
 }
 
+/// Open a pin scope on `mutator`, e.g. around a native call. Scopes nest.
+#[cfg(feature = "object_pinning")]
+#[no_mangle]
+pub extern "C" fn mmtk_pin_scope_enter(mutator: *mut Mutator<ScalaNative>) {
+    PIN_SCOPES.enter(mutator);
+}
+
+/// Pin the object at `addr` until the innermost pin scope of `mutator` exits.
+/// Returns false if it was already pinned, in which case the scope does not unpin it.
+/// `mutator` must be in managed state: pin before `mmtk_enter_native`.
+#[cfg(feature = "object_pinning")]
+#[no_mangle]
+pub extern "C" fn mmtk_pin_in_scope(mutator: *mut Mutator<ScalaNative>, addr: *mut word_t) -> bool {
+    PIN_SCOPES.pin(mutator, ObjectReference::from_raw_address(Address::from_mut_ptr(addr)))
+}
+
+/// Close the innermost pin scope of `mutator` and unpin the objects pinned in it.
+#[cfg(feature = "object_pinning")]
+#[no_mangle]
+pub extern "C" fn mmtk_pin_scope_exit(mutator: *mut Mutator<ScalaNative>) {
+    PIN_SCOPES.exit(mutator);
+}
+
+/// Pin objects until the end of the current GC, on behalf of the runtime.
 #[cfg(feature = "object_pinning")]
 #[no_mangle]
 pub extern "C" fn mmtk_append_pinned_objects(data: *const *const usize, len: size_t) {
@@ -82,6 +172,51 @@ pub extern "C" fn mmtk_append_pinned_objects(data: *const *const usize, len: siz
     pinned_objects.append(&mut vec)
 }
 
+#[no_mangle]
+pub extern "C" fn mmtk_add_roots(start: Address, end: Address) {
+    ROOT_RANGES.add(start.as_usize(), end.as_usize());
+}
+
+#[no_mangle]
+pub extern "C" fn mmtk_remove_roots(start: Address, end: Address) {
+    ROOT_RANGES.remove(start.as_usize(), end.as_usize());
+}
+
+#[no_mangle]
+pub extern "C" fn mmtk_handle_new(object: ObjectReference) -> Address {
+    HANDLES.new_handle(object)
+}
+
+#[no_mangle]
+pub extern "C" fn mmtk_handle_get(handle: Address) -> ObjectReference {
+    HandleTable::get(handle)
+}
+
+#[no_mangle]
+pub extern "C" fn mmtk_handle_free(handle: Address) {
+    HANDLES.free_handle(handle)
+}
+
+/// Register the rtti id range of the classes used for captured stack fragments.
+#[no_mangle]
+pub extern "C" fn mmtk_register_stack_chunk_ids(min: i32, max: i32) {
+    crate::abi::register_stack_chunk_ids(min, max);
+}
+
+/// Choose whether stack chunks are pinned or may be moved by the GC.
+/// Pinning them is only supported with the `object_pinning` feature.
+#[no_mangle]
+pub extern "C" fn mmtk_set_pin_stack_chunks(pin: bool) {
+    assert!(!pin || cfg!(feature = "object_pinning"), "Pinning stack chunks requires the object_pinning feature");
+    crate::abi::PIN_STACK_CHUNKS.store(pin, Ordering::SeqCst);
+}
+
+/// Register the rtti id range of ephemeron classes and the field indices of their key and value.
+#[no_mangle]
+pub extern "C" fn mmtk_register_ephemerons(min: i32, max: i32, key_offset: i32, value_offset: i32) {
+    crate::abi::register_ephemerons(min, max, key_offset, value_offset);
+}
+
 #[no_mangle]
 pub extern "C" fn mmtk_init_binding(upcalls: *const ScalaNativeUpcalls) {
     let binding = ScalaNativeBinding::new(&SINGLETON, upcalls);
@@ -90,17 +225,61 @@ pub extern "C" fn mmtk_init_binding(upcalls: *const ScalaNativeUpcalls) {
 
 #[no_mangle]
 pub extern "C" fn mmtk_bind_mutator(tls: VMMutatorThread) -> *mut Mutator<ScalaNative> {
-    Box::into_raw(memory_manager::bind_mutator(&SINGLETON, tls))
+    let mutator = Box::into_raw(memory_manager::bind_mutator(&SINGLETON, tls));
+    MUTATORS.register(tls, mutator);
+    mutator
 }
 
 #[no_mangle]
 pub extern "C" fn mmtk_destroy_mutator(mutator: *mut Mutator<ScalaNative>) {
+    // a thread may exit in the middle of pin scopes
+    #[cfg(feature = "object_pinning")]
+    PIN_SCOPES.release_mutator(mutator);
+    MUTATORS.unregister(mutator);
     // notify mmtk-core about destroyed mutator
     memory_manager::destroy_mutator(unsafe { &mut *mutator });
     // turn the ptr back to a box, and let Rust properly reclaim it
     let _ = unsafe { Box::from_raw(mutator) };
 }
 
+/// Bind a mutator for the calling thread, which the runtime did not start, e.g. a foreign
+/// thread calling into Scala. The stack bounds of the thread are recorded. Returns the
+/// mutator already attached if the thread attached itself before.
+#[no_mangle]
+pub extern "C" fn mmtk_attach_current_thread(tls: VMMutatorThread) -> *mut Mutator<ScalaNative> {
+    if let Some(mutator) = MUTATORS.attached_to_current_thread() {
+        return mutator;
+    }
+    let mutator = Box::into_raw(memory_manager::bind_mutator(&SINGLETON, tls));
+    MUTATORS.register_current_thread(tls, mutator);
+    mutator
+}
+
+/// Destroy the mutator attached by the calling thread. Returns false if it was not attached.
+#[no_mangle]
+pub extern "C" fn mmtk_detach_current_thread() -> bool {
+    match MUTATORS.attached_to_current_thread() {
+        Some(mutator) => {
+            mmtk_destroy_mutator(mutator);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Copy the stack bounds of the thread of `mutator` to `bounds`.
+/// Returns false if they were not recorded, as for the threads started by the runtime.
+#[no_mangle]
+pub extern "C" fn mmtk_get_mutator_stack_bounds(mutator: *mut Mutator<ScalaNative>, bounds: *mut StackBounds) -> bool {
+    match MUTATORS.stack_bounds(mutator) {
+        Some(stack) => {
+            unsafe { *bounds = stack };
+            true
+        }
+        None => false,
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn mmtk_flush_mutator(mutator: *mut Mutator<ScalaNative>) {
     memory_manager::flush_mutator(unsafe { &mut *mutator });
@@ -197,8 +376,14 @@ pub extern "C" fn mmtk_is_mapped_address(address: Address) -> bool {
     memory_manager::is_mapped_address(address)
 }
 
+/// Run a GC and wait for it. Does nothing in a forked child, which has no GC threads.
 #[no_mangle]
 pub extern "C" fn mmtk_handle_user_collection_request(tls: VMMutatorThread) {
+    #[cfg(unix)]
+    if crate::fork::is_forked_child() {
+        debug!("Ignoring a collection request in a forked child");
+        return;
+    }
     memory_manager::handle_user_collection_request::<ScalaNative>(&SINGLETON, tls);
     // memory_manager::handle_user_collection_request::<ScalaNative>(&SINGLETON, tls, false);
 }
@@ -208,16 +393,60 @@ pub extern "C" fn mmtk_add_weak_candidate(reff: ObjectReference) {
     memory_manager::add_weak_candidate(&SINGLETON, reff)
 }
 
+#[cfg(feature = "mmtk_reference_processor")]
 #[no_mangle]
 pub extern "C" fn mmtk_add_soft_candidate(reff: ObjectReference) {
     memory_manager::add_soft_candidate(&SINGLETON, reff)
 }
 
+/// Without `mmtk_reference_processor`, soft references are retained by the binding's policy
+/// instead of MMTk's reference processor. See `SoftReferences`.
+#[cfg(not(feature = "mmtk_reference_processor"))]
+#[no_mangle]
+pub extern "C" fn mmtk_add_soft_candidate(reff: ObjectReference) {
+    SOFT_REFERENCES.register(reff)
+}
+
+/// Register the field index, in soft references, of a 64-bit field the binding uses to
+/// record the last access. Soft references registered before are only treated as weak references.
+#[no_mangle]
+pub extern "C" fn mmtk_register_soft_ref_timestamp(offset: i32) {
+    crate::abi::register_soft_ref_timestamp(offset);
+}
+
+/// Record an access to a soft reference. Soft references accessed recently are the last
+/// to be cleared under heap pressure.
+#[no_mangle]
+pub extern "C" fn mmtk_soft_ref_accessed(reff: ObjectReference) {
+    #[cfg(not(feature = "mmtk_reference_processor"))]
+    SOFT_REFERENCES.touch(reff);
+    #[cfg(feature = "mmtk_reference_processor")]
+    let _ = reff;
+}
+
+/// Retain soft referents while at least `free_heap_percent` of the heap was free after the
+/// last GC. Otherwise, clear those not accessed during the last `max_idle_gcs` GCs.
+#[no_mangle]
+pub extern "C" fn mmtk_set_soft_ref_policy(free_heap_percent: usize, max_idle_gcs: usize) {
+    SOFT_REFERENCES.set_policy(free_heap_percent, max_idle_gcs as u64)
+}
+
 #[no_mangle]
 pub extern "C" fn mmtk_add_phantom_candidate(reff: ObjectReference) {
     memory_manager::add_phantom_candidate(&SINGLETON, reff)
 }
 
+#[no_mangle]
+pub extern "C" fn mmtk_add_finalizer(object: ObjectReference) {
+    memory_manager::add_finalizer(&SINGLETON, object)
+}
+
+/// Pop an object that is ready for finalization, or NULL if there is none.
+#[no_mangle]
+pub extern "C" fn mmtk_get_finalized_object() -> ObjectReference {
+    memory_manager::get_finalized_object(&SINGLETON).unwrap_or(ObjectReference::NULL)
+}
+
 #[no_mangle]
 pub extern "C" fn mmtk_harness_begin(tls: VMMutatorThread) {
     memory_manager::harness_begin(&SINGLETON, tls)
@@ -294,69 +523,122 @@ pub extern "C" fn get_max_non_los_default_alloc_bytes() -> usize {
         .max_non_los_default_alloc_bytes
 }
 
-// Define types for our requests and responses
-pub enum SyncRequest {
-    Acquire(VMWorkerThread),
-    Release(VMWorkerThread),
-}
-
-pub enum SyncResponse {
-    Acquired,
-    Released,
-}
-
-// Define global channels
-lazy_static! {
-    pub static ref REQ_SENDER: Mutex<mpsc::Sender<SyncRequest>> = {
-        let (sender, _) = mpsc::channel();
-        Mutex::new(sender)
-    };
-    pub static ref RES_RECEIVER: Mutex<mpsc::Receiver<SyncResponse>> = {
-        let (_, receiver) = mpsc::channel();
-        Mutex::new(receiver)
-    };
-}
+static SYNCHRONIZER_THREAD: Mutex<Option<JoinHandle<()>>> = Mutex::new(None);
 
 #[no_mangle]
 pub extern "C" fn scalanative_gc_init(calls: *const ScalaNativeUpcalls) {
     unsafe { UPCALLS = calls };
-    // Create channels for request and response
-    let (req_tx, req_rx) = mpsc::channel::<SyncRequest>();
-    let (res_tx, res_rx) = mpsc::channel::<SyncResponse>();
 
-    // Overwrite the global channels with the ones we just created
-    *REQ_SENDER.lock().unwrap() = req_tx;
-    *RES_RECEIVER.lock().unwrap() = res_rx;
+    // Spawn a dedicated thread that stops and resumes the mutators, as the runtime
+    // takes and releases its lock on the same thread. A single-threaded runtime has
+    // nothing to stop: the GC calls the upcalls directly.
+    #[cfg(feature = "scalanative_multithreading_enabled")]
+    spawn_synchronizer_thread();
+
+    #[cfg(unix)]
+    crate::fork::register_fork_handlers();
+}
+
+/// Start a new synchronizer thread in a forked child, forgetting the one of the parent.
+#[cfg(feature = "scalanative_multithreading_enabled")]
+pub(crate) fn respawn_synchronizer_thread() {
+    std::mem::forget(SYNCHRONIZER_THREAD.lock().unwrap().take());
+    spawn_synchronizer_thread();
+}
 
-    // Spawn a dedicated thread that owns the lock
-    thread::Builder::new()
+#[cfg(feature = "scalanative_multithreading_enabled")]
+fn spawn_synchronizer_thread() {
+    let synchronizer = thread::Builder::new()
         .name("MMTk Synchronizer Thread".to_string())
         .spawn(move || {
             debug!("Hello! This is MMTk Synchronizer Thread running!");
             crate::register_gc_thread(thread::current().id());
             unsafe { ((*UPCALLS).init_synchronizer_thread)()};
-    
-            let lock = Mutex::new(());
-            for req in req_rx {
-                match req {
-                    SyncRequest::Acquire(_tls) => {
-                        let _guard = lock.lock().unwrap();
-                        unsafe { ((*UPCALLS).stop_all_mutators)(_tls) };
-                        res_tx.send(SyncResponse::Acquired).unwrap();
-                    }
-                    SyncRequest::Release(tls) => {
-                        unsafe { ((*UPCALLS).resume_mutators)(tls) };
-                        res_tx.send(SyncResponse::Released).unwrap();
-                    }
-                }
-            }
-    
-            // Currently the MMTk controller thread should run forever.
-            // This is an unlikely event, but we log it anyway.
-            warn!("The MMTk Controller Thread is quitting!");
+
+            SAFEPOINT.serve(
+                |tls| unsafe { ((*UPCALLS).stop_all_mutators)(tls) },
+                |tls| unsafe { ((*UPCALLS).resume_mutators)(tls) },
+                || MUTATORS.managed(),
+            );
+
+            debug!("The MMTk Synchronizer Thread is quitting");
             crate::unregister_gc_thread(thread::current().id());
         })
         .unwrap();
+    *SYNCHRONIZER_THREAD.lock().unwrap() = Some(synchronizer);
+}
+
+/// Run the reference handlers and schedule the finalizers after a GC, in single-threaded mode.
+/// There is no reference handler thread, so the runtime calls it at a safe point, e.g. once an
+/// allocation that may have collected returns. Does nothing if no GC ran since the last call.
+#[cfg(not(feature = "scalanative_multithreading_enabled"))]
+#[no_mangle]
+pub extern "C" fn mmtk_run_pending_handlers() {
+    crate::collection::run_pending_handlers();
+}
+
+/// Stop collecting, and stop the synchronizer and reference handler threads of the binding.
+///
+/// Waits for a collection in flight to resume the mutators, then disables collection. The
+/// synchronizer thread exits, then the reference handler thread runs the pending handlers and
+/// exits. The mutators may keep allocating afterwards, but no new collection starts. One
+/// requested just before collection was disabled may still run: the GC threads then stop and
+/// resume the mutators themselves, see `Safepoint::serve_in_place`.
+///
+/// This is not a shutdown. mmtk-core 0.21 provides no way to stop the controller and worker
+/// threads, so they stay parked and their `GCThreadTLS` is never freed, nor to unmap the heap.
+#[no_mangle]
+pub extern "C" fn mmtk_stop_collection() {
+    {
+        let _idle = GC_IN_FLIGHT.wait_until_idle();
+        memory_manager::disable_collection(&SINGLETON);
+    }
+    SAFEPOINT.shutdown();
+    if let Some(synchronizer) = SYNCHRONIZER_THREAD.lock().unwrap().take() {
+        synchronizer.join().unwrap();
+    }
+    REFERENCE_HANDLER.shutdown();
+}
+
+/// Called by each mutator when it reaches a safepoint. A GC waits for every mutator in managed
+/// state to call it, and measures the time to safepoint from the calls.
+#[no_mangle]
+pub extern "C" fn mmtk_safepoint_acknowledge(tls: VMMutatorThread) {
+    SAFEPOINT.acknowledge(tls);
+}
+
+/// The mutator enters a GC-safe region, e.g. before a blocking native call. It must have saved
+/// its stack range: collections proceed without waiting for it and scan that range instead.
+#[no_mangle]
+pub extern "C" fn mmtk_enter_native(mutator: *mut Mutator<ScalaNative>) {
+    MUTATORS.enter_native(unsafe { (*mutator).get_tls() }, &SAFEPOINT);
+}
+
+/// The mutator leaves its GC-safe region. Blocks until any collection in progress has finished.
+#[no_mangle]
+pub extern "C" fn mmtk_exit_native(mutator: *mut Mutator<ScalaNative>) {
+    MUTATORS.exit_native(unsafe { (*mutator).get_tls() }, &SAFEPOINT);
+}
+
+#[no_mangle]
+pub extern "C" fn mmtk_get_safepoint_stats(stats: *mut SafepointStats) {
+    unsafe { *stats = SAFEPOINT.stats() };
+}
+
+/// Copy at most `len` buckets of the time-to-safepoint histogram to `buckets`.
+/// Returns the number of buckets of the histogram.
+#[no_mangle]
+pub extern "C" fn mmtk_get_safepoint_histogram(buckets: *mut u64, len: usize) -> usize {
+    let histogram = SAFEPOINT.histogram();
+    let len = len.min(histogram.len());
+    unsafe { std::ptr::copy_nonoverlapping(histogram.as_ptr(), buckets, len) };
+    histogram.len()
+}
+
+/// How long a GC waits for the mutators to stop or resume before it warns about them.
+#[no_mangle]
+pub extern "C" fn mmtk_set_safepoint_timeout(timeout_ms: u64) {
+    SAFEPOINT_TIMEOUT_MS.store(timeout_ms, Ordering::SeqCst);
 }
 
 /// # Safety
@@ -377,6 +659,12 @@ pub extern "C" fn invoke_mutator_closure(closure: *mut MutatorClosure, mutator:
     (closure.func)(mutator, &closure.data);
 }
 
+#[no_mangle]
+pub extern "C" fn invoke_ranges_closure(closure: *mut RangesClosure, start: *mut *mut usize, end: *mut *mut usize) {
+    let closure = unsafe { &mut *closure };
+    (closure.func)(start, end, &closure.data);
+}
+
 #[no_mangle]
 pub extern "C" fn visit_edge(closure_ptr: *mut std::ffi::c_void, edge: Address) {
     let closure = unsafe { &mut *(closure_ptr as *mut ClosureWrapper<ScalaNativeEdge>) };
@@ -412,3 +700,10 @@ pub extern "C" fn mmtk_weak_ref_stack_set_handler(handler: *mut c_void) {
     let handler_fn = unsafe { std::mem::transmute::<*mut c_void, fn()>(handler) };
     *HANDLER_FN.lock().unwrap() = Some(handler_fn);
 }
+
+/// Register the callback receiving the references cleared by each GC, as a pointer and a length.
+/// It is called after the mutators are resumed. Passing NULL removes the callback.
+#[no_mangle]
+pub extern "C" fn mmtk_set_reference_queue_handler(handler: Option<extern "C" fn(references: *const *mut Object, len: usize)>) {
+    *REFERENCE_QUEUE_HANDLER.lock().unwrap() = handler;
+}
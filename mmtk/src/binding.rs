@@ -1,6 +1,8 @@
 use std::sync::Mutex;
 
 use mmtk::{MMTK, util::ObjectReference};
+#[cfg(feature = "object_pinning")]
+use mmtk::memory_manager;
 
 use crate::{ScalaNative, ScalaNativeUpcalls};
 
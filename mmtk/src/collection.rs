@@ -1,7 +1,13 @@
 use crate::MutatorClosure;
 use crate::SINGLETON;
 use crate::ScalaNative;
-use crate::api::{SyncRequest, REQ_SENDER};
+#[cfg(feature = "scalanative_multithreading_enabled")]
+use crate::safepoint::{safepoint_timeout, HandshakeError, Phase, SAFEPOINT};
+#[cfg(feature = "scalanative_multithreading_enabled")]
+use crate::mutators::MutatorState;
+#[cfg(feature = "scalanative_multithreading_enabled")]
+use crate::mutators::MUTATORS;
+use crate::active_plan::VMActivePlan;
 use crate::UPCALLS;
 use log::debug;
 use log::warn;
@@ -9,65 +15,209 @@ use mmtk::memory_manager;
 use mmtk::util::alloc::AllocationError;
 use mmtk::util::opaque_pointer::*;
 use mmtk::Mutator;
-use mmtk::vm::{Collection, GCThreadContext};
+use mmtk::vm::{ActivePlan, Collection, GCThreadContext};
 
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
 use std::thread;
 use mmtk::scheduler::*;
 use crate::abi::GCThreadTLS;
+use crate::soft_refs::SOFT_REFERENCES;
+#[cfg(feature = "scalanative_multithreading_enabled")]
+use crate::reference_handler::REFERENCE_HANDLER;
+#[cfg(not(feature = "scalanative_multithreading_enabled"))]
+use crate::reference_handler::handle_references;
+use crate::gc_threads::{configure_current_thread, worker_thread_name};
 
 pub struct VMCollection {}
 
+/// Set when MMTk found objects to finalize during a GC. The finalizer upcall is made by the
+/// reference handler thread once the mutators are resumed, so the runtime may run finalizers right away.
+/// In single-threaded mode, the mutator makes it in `run_pending_handlers`.
+static FINALIZATION_SCHEDULED: AtomicBool = AtomicBool::new(false);
+
+/// Set by each GC in single-threaded mode, until the mutator runs the reference handlers.
+#[cfg(not(feature = "scalanative_multithreading_enabled"))]
+static HANDLERS_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Set while the mutator runs the reference handlers, in single-threaded mode.
+#[cfg(not(feature = "scalanative_multithreading_enabled"))]
+static HANDLING_REFERENCES: AtomicBool = AtomicBool::new(false);
+
+/// Run the reference handlers if a GC ran since the last call, in single-threaded mode. The
+/// mutator calls it at a safe point, not from `block_for_gc`, which runs in the allocation slow
+/// path. The handlers may allocate and trigger another GC, whose handlers wait for the next call.
+#[cfg(not(feature = "scalanative_multithreading_enabled"))]
+pub(crate) fn run_pending_handlers() {
+    if !HANDLERS_PENDING.load(Ordering::SeqCst) || HANDLING_REFERENCES.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    HANDLERS_PENDING.store(false, Ordering::SeqCst);
+    handle_references(FINALIZATION_SCHEDULED.swap(false, Ordering::SeqCst));
+    HANDLING_REFERENCES.store(false, Ordering::SeqCst);
+}
+
+/// Tracks a collection from `stop_all_mutators` until `resume_mutators` returns, so the binding
+/// can wait for it before disabling collection or forking.
+///
+/// mmtk-core 0.21 does not tell the binding when a collection is requested, so a collection
+/// requested but not yet stopping the mutators is not in flight. It waits in `begin` while
+/// `wait_until_idle` holds the guard.
+pub(crate) struct GcInFlight {
+    in_flight: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl GcInFlight {
+    const fn new() -> Self {
+        Self {
+            in_flight: Mutex::new(false),
+            condvar: Condvar::new(),
+        }
+    }
+
+    fn begin(&self) {
+        *self.in_flight.lock().unwrap() = true;
+    }
+
+    fn end(&self) {
+        *self.in_flight.lock().unwrap() = false;
+        self.condvar.notify_all();
+    }
+
+    /// Wait until no collection is in flight. No collection starts while the guard is held.
+    pub(crate) fn wait_until_idle(&self) -> MutexGuard<'_, bool> {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        while *in_flight {
+            in_flight = self.condvar.wait(in_flight).unwrap();
+        }
+        in_flight
+    }
+}
+
+pub(crate) static GC_IN_FLIGHT: GcInFlight = GcInFlight::new();
+
 pub const GC_THREAD_KIND_CONTROLLER: libc::c_int = 0;
 pub const GC_THREAD_KIND_WORKER: libc::c_int = 1;
-lazy_static! {
-    pub static ref OFFSET_OF_MUTATOR_CONTEXT: isize = unsafe {
-        ((*UPCALLS).get_mutator_context_offset)() as isize
-    };
-}
 
 #[repr(C)]
 pub struct SendCtxPtr(*mut libc::c_void);
 
 unsafe impl Send for SendCtxPtr {}
 
+/// Wait for the synchronizer thread to reach `phase`. The mutators may take a while to reach
+/// a safepoint, e.g. during a long native call, so a timeout is only reported.
+#[cfg(feature = "scalanative_multithreading_enabled")]
+fn wait_for_safepoint(phase: Phase) {
+    loop {
+        match SAFEPOINT.wait_for(phase, safepoint_timeout()) {
+            Ok(()) => return,
+            Err(err @ HandshakeError::Timeout(..)) => {
+                warn!("Still waiting for the mutators: {}", err);
+                if phase == Phase::Stopped {
+                    warn_about_running_mutators();
+                }
+            }
+            Err(err) => panic!("Failed to wait for the mutators: {}", err),
+        }
+    }
+}
+
+/// Make the upcalls for a request on this GC thread, once `mmtk_stop_collection` stopped the synchronizer thread.
+#[cfg(feature = "scalanative_multithreading_enabled")]
+fn serve_in_place(tls: VMWorkerThread, requested: Phase) {
+    debug!("Serving {:?} on a GC thread after shutdown", requested);
+    let result = SAFEPOINT.serve_in_place(
+        tls,
+        requested,
+        |tls| unsafe { ((*UPCALLS).stop_all_mutators)(tls) },
+        |tls| unsafe { ((*UPCALLS).resume_mutators)(tls) },
+        || MUTATORS.managed(),
+    );
+    if let Err(err) = result {
+        panic!("Failed to serve {:?}: {}", requested, err);
+    }
+}
+
+/// Log the threads that did not reach the safepoint yet, with their stack range.
+#[cfg(feature = "scalanative_multithreading_enabled")]
+fn warn_about_running_mutators() {
+    for entry in MUTATORS.entries() {
+        // Threads in a GC-safe region count as stopped.
+        if !SAFEPOINT.is_acknowledged(entry.tls) && entry.state != MutatorState::Native {
+            let stack_range = unsafe { ((*UPCALLS).get_stack_range)(entry.tls) };
+            warn!(
+                "Mutator {:?} has not reached the safepoint, stack: {:p} - {:p}",
+                entry.tls, stack_range.stack_top, stack_range.stack_bottom
+            );
+        }
+    }
+}
+
 impl Collection<ScalaNative> for VMCollection {
-    fn stop_all_mutators<F>(tls: VMWorkerThread, mut _mutator_visitor: F)
+    fn stop_all_mutators<F>(tls: VMWorkerThread, mut mutator_visitor: F)
     where
         F: FnMut(&'static mut Mutator<ScalaNative>),
     {
-        let result = REQ_SENDER.lock().unwrap().send(SyncRequest::Acquire(tls));
-        match result {
-            Err(err) => println!("Failed to send message: {:?}", err),
-            _ => ()
+        GC_IN_FLIGHT.begin();
+        #[cfg(feature = "scalanative_multithreading_enabled")]
+        {
+            match SAFEPOINT.request_stop(tls) {
+                // Nothing may be scanned before every mutator is stopped.
+                Ok(()) => wait_for_safepoint(Phase::Stopped),
+                // Collection was disabled after this GC was requested.
+                Err(HandshakeError::ShutDown) => serve_in_place(tls, Phase::StopRequested),
+                Err(err) => panic!("Failed to stop the mutators: {}", err),
+            }
+        }
+        // The only mutator requested this GC and waits in `block_for_gc`, so there is no handshake.
+        #[cfg(not(feature = "scalanative_multithreading_enabled"))]
+        unsafe {
+            ((*UPCALLS).stop_all_mutators)(tls);
+        }
+        // Threads in native state are visited as well: their stacks are scanned from the saved range.
+        // The mutators are the ones MMTk knows of, in single-threaded mode the single mutator.
+        for mutator in VMActivePlan::mutators() {
+            mutator_visitor(mutator);
         }
-        // unsafe {
-        //     let mut head = ((*UPCALLS).get_mutator_threads)();
-        //     while !head.is_null() {
-        //         println!("head: {:p}", head);
-        //         let node = &*head;
-        //         let thread = node.value;
-        //         let mutator_context = thread.offset(*OFFSET_OF_MUTATOR_CONTEXT);
-        //         println!("Offset of mutator context: {}", *OFFSET_OF_MUTATOR_CONTEXT);
-        //         println!("calling closure on thread {:p}", mutator_context);
-        //         let closure = MutatorClosure::from_rust_closure(&mut _mutator_visitor);
-        //         (closure.func)(mutator_context as *mut Mutator<ScalaNative>, &closure.data);
-        //         head = node.next;
-        //     }
-        // }
     }
 
     fn resume_mutators(tls: VMWorkerThread) {
-        let result = REQ_SENDER.lock().unwrap().send(SyncRequest::Release(tls));
-        match result {
-            Err(err) => println!("Failed to send message: {:?}", err),
-            _ => ()
-        }        
+        crate::scanning::seal_cleared_references();
+        SOFT_REFERENCES.record_free_heap(
+            memory_manager::free_bytes(&SINGLETON),
+            memory_manager::total_bytes(&SINGLETON),
+        );
+        #[cfg(feature = "scalanative_multithreading_enabled")]
+        {
+            match SAFEPOINT.request_resume(tls) {
+                Ok(()) => wait_for_safepoint(Phase::Running),
+                Err(HandshakeError::ShutDown) => serve_in_place(tls, Phase::ResumeRequested),
+                Err(err) => panic!("Failed to resume the mutators: {}", err),
+            }
+            REFERENCE_HANDLER.wake(FINALIZATION_SCHEDULED.swap(false, Ordering::SeqCst));
+        }
+        #[cfg(not(feature = "scalanative_multithreading_enabled"))]
+        {
+            HANDLERS_PENDING.store(true, Ordering::SeqCst);
+            unsafe { ((*UPCALLS).resume_mutators)(tls) };
+        }
+        GC_IN_FLIGHT.end();
     }
 
+    /// Running the GC on the mutator thread is not supported: mmtk-core 0.21 only executes work
+    /// packets on its `GCWorker`s. In single-threaded mode, the GC also runs on the controller
+    /// and worker threads while the mutator waits here.
     fn block_for_gc(tls: VMMutatorThread) {
+        // The thread waits for the GC in a GC-safe region, so the GC does not wait for it to
+        // reach a safepoint, which it may have been asked to before or after this call.
+        #[cfg(feature = "scalanative_multithreading_enabled")]
+        MUTATORS.enter_native(tls, &SAFEPOINT);
         unsafe {
             ((*UPCALLS).block_for_gc)(tls);
         }
+        #[cfg(feature = "scalanative_multithreading_enabled")]
+        MUTATORS.exit_native(tls, &SAFEPOINT);
     }
 
     fn spawn_gc_thread(_tls: VMThread, ctx: GCThreadContext<ScalaNative>) {
@@ -81,6 +231,7 @@ impl Collection<ScalaNative> for VMCollection {
                     .spawn(move || {
                         debug!("Hello! This is MMTk Controller Thread running!");
                         crate::register_gc_thread(thread::current().id());
+                        configure_current_thread(None);
                         let ptr_controller = &mut *controller as *mut GCController<ScalaNative>;
                         let gc_thread_tls =
                             Box::into_raw(Box::new(GCThreadTLS::for_controller(ptr_controller)));
@@ -104,14 +255,17 @@ impl Collection<ScalaNative> for VMCollection {
                 let ctx_ptr = &*worker as *const _ as *mut libc::c_void;
                 let send_ctx_ptr = SendCtxPtr(ctx_ptr);
             
+                let ordinal = worker.ordinal;
                 thread::Builder::new()
-                    .name("MMTk Worker Thread".to_string())
+                    .name(worker_thread_name(ordinal))
                     .spawn(move || {
-                        debug!("Hello! This is MMTk Worker Thread running!");
+                        debug!("Hello! This is MMTk Worker Thread {} running!", ordinal);
                         crate::register_gc_thread(thread::current().id());
+                        configure_current_thread(Some(ordinal));
                         let ptr_worker = &mut *worker as *mut GCWorker<ScalaNative>;
                         let gc_thread_tls =
                             Box::into_raw(Box::new(GCThreadTLS::for_worker(ptr_worker)));
+                        crate::scanning::register_weak_ref_buffer(unsafe { &(*gc_thread_tls).weak_refs });
                         (unsafe { (*UPCALLS).init_gc_worker_thread })(gc_thread_tls, send_ctx_ptr);
                         memory_manager::start_worker(
                             &SINGLETON,
@@ -158,9 +312,7 @@ impl Collection<ScalaNative> for VMCollection {
     }
 
     fn schedule_finalization(_tls: VMWorkerThread) {
-        unsafe {
-            ((*UPCALLS).schedule_finalizer)();
-        }
+        FINALIZATION_SCHEDULED.store(true, Ordering::SeqCst);
     }
 
     fn post_forwarding(_tls: VMWorkerThread) {}
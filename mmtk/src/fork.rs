@@ -0,0 +1,97 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{MutexGuard, Once};
+use std::thread::ThreadId;
+
+use log::warn;
+use mmtk::memory_manager;
+
+use crate::collection::GC_IN_FLIGHT;
+use crate::mutators::{MutatorRegistryForkGuard, MUTATORS};
+use crate::reference_handler::{ReferenceHandlerForkGuard, REFERENCE_HANDLER};
+use crate::safepoint::{SafepointForkGuard, SAFEPOINT};
+use crate::{GC_THREADS, SINGLETON};
+
+/// The locks of the binding, held by the forking thread from the prepare handler until the
+/// parent or child handler, so the child never inherits a lock held by a thread it lacks.
+struct ForkGuards {
+    gc_idle: MutexGuard<'static, bool>,
+    safepoint: SafepointForkGuard,
+    reference_handler: ReferenceHandlerForkGuard,
+    mutators: MutatorRegistryForkGuard,
+    gc_threads: MutexGuard<'static, HashSet<ThreadId>>,
+}
+
+thread_local! {
+    static FORK_GUARDS: RefCell<Option<ForkGuards>> = RefCell::new(None);
+}
+
+static REGISTER: Once = Once::new();
+
+/// Set in a child process forked after `scalanative_gc_init`.
+static FORKED: AtomicBool = AtomicBool::new(false);
+
+/// Register the `pthread_atfork` handlers. Called by `scalanative_gc_init`.
+///
+/// The thread calling `fork` must be in a GC-safe region (see `mmtk_enter_native`), or a GC
+/// waiting for it to stop would never let the prepare handler proceed.
+///
+/// The child only has the forking thread. The binding resets its own state and respawns the
+/// synchronizer thread. Respawning the controller and worker threads is not supported, as
+/// mmtk-core 0.21 only spawns them in `initialize_collection`, so collection is disabled in the
+/// child: its heap grows until it exits or execs, and `out_of_memory` is called if it fills up.
+pub fn register_fork_handlers() {
+    REGISTER.call_once(|| {
+        let result = unsafe { libc::pthread_atfork(Some(prepare), Some(parent), Some(child)) };
+        if result != 0 {
+            warn!("Failed to register the fork handlers: {}", std::io::Error::from_raw_os_error(result));
+        }
+    });
+}
+
+extern "C" fn prepare() {
+    // Wait for a GC in flight first, so none of the locks below is held by a GC thread, and
+    // keep a new GC from stopping the mutators until the fork is done.
+    let gc_idle = GC_IN_FLIGHT.wait_until_idle();
+    let safepoint = SAFEPOINT.prepare_fork();
+    let reference_handler = REFERENCE_HANDLER.prepare_fork();
+    let mutators = MUTATORS.prepare_fork();
+    let gc_threads = GC_THREADS.get_or_init(Default::default).lock().unwrap();
+    FORK_GUARDS.with(|guards| {
+        *guards.borrow_mut() = Some(ForkGuards {
+            gc_idle,
+            safepoint,
+            reference_handler,
+            mutators,
+            gc_threads,
+        })
+    });
+}
+
+extern "C" fn parent() {
+    FORK_GUARDS.with(|guards| guards.borrow_mut().take());
+}
+
+extern "C" fn child() {
+    let Some(mut guards) = FORK_GUARDS.with(|guards| guards.borrow_mut().take()) else {
+        return;
+    };
+    guards.gc_threads.clear();
+    drop(guards.gc_threads);
+    guards.mutators.reset_in_child();
+    guards.reference_handler.reset_in_child();
+    guards.safepoint.reset_in_child();
+
+    FORKED.store(true, Ordering::SeqCst);
+    memory_manager::disable_collection(&SINGLETON);
+    drop(guards.gc_idle);
+    #[cfg(feature = "scalanative_multithreading_enabled")]
+    crate::api::respawn_synchronizer_thread();
+    warn!("The GC threads do not survive fork(): collection is disabled in the child process");
+}
+
+/// Whether this is a forked child, which has no GC threads to run a collection.
+pub(crate) fn is_forked_child() -> bool {
+    FORKED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,127 @@
+use std::sync::Mutex;
+
+use libc::c_int;
+use log::warn;
+
+/// Placement of the GC controller and worker threads, set through the C API before
+/// `initialize_collection` spawns them.
+pub static GC_THREAD_OPTIONS: Mutex<GCThreadOptions> = Mutex::new(GCThreadOptions::new());
+
+/// The environment variable that sets the number of GC workers, as in the Boehm GC.
+pub const GC_NPROCS: &str = "GC_NPROCS";
+
+pub struct GCThreadOptions {
+    /// The CPUs the GC threads run on. Worker `i` is pinned to `cpus[i % cpus.len()]` and the
+    /// controller may run on any of them. Empty means no affinity.
+    pub cpus: Vec<usize>,
+    /// The scheduling policy and priority of the GC threads, as for `pthread_setschedparam`.
+    pub policy: Option<(c_int, c_int)>,
+}
+
+impl GCThreadOptions {
+    pub const fn new() -> Self {
+        Self {
+            cpus: Vec::new(),
+            policy: None,
+        }
+    }
+}
+
+impl Default for GCThreadOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The number of workers requested by `GC_NPROCS`, if it is set to a positive number.
+pub fn gc_nprocs() -> Option<usize> {
+    let value = std::env::var(GC_NPROCS).ok()?;
+    match value.trim().parse::<usize>() {
+        Ok(n) if n > 0 => Some(n),
+        _ => {
+            warn!("Ignoring {}={:?}: expected a positive number", GC_NPROCS, value);
+            None
+        }
+    }
+}
+
+/// Parse a CPU list such as "0-3,6", as in `taskset -c`.
+pub fn parse_cpu_list(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for range in list.split(',').map(str::trim).filter(|range| !range.is_empty()) {
+        match range.split_once('-') {
+            Some((first, last)) => {
+                let first = first.trim().parse::<usize>().ok()?;
+                let last = last.trim().parse::<usize>().ok()?;
+                if first > last {
+                    return None;
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(range.parse::<usize>().ok()?),
+        }
+    }
+    if cpus.is_empty() {
+        None
+    } else {
+        Some(cpus)
+    }
+}
+
+pub fn worker_thread_name(ordinal: usize) -> String {
+    format!("MMTk Worker Thread {}", ordinal)
+}
+
+/// Apply the affinity and scheduling options to the calling GC thread. `ordinal` is the
+/// index of a worker, or `None` for the controller. Failures are logged and ignored, so a
+/// misconfiguration never stops the GC.
+pub fn configure_current_thread(ordinal: Option<usize>) {
+    let options = GC_THREAD_OPTIONS.lock().unwrap();
+    if !options.cpus.is_empty() {
+        let cpus = match ordinal {
+            Some(ordinal) => &options.cpus[ordinal % options.cpus.len()..][..1],
+            None => &options.cpus[..],
+        };
+        if let Err(err) = set_current_thread_affinity(cpus) {
+            warn!("Failed to set the affinity of a GC thread to {:?}: {}", cpus, err);
+        }
+    }
+    if let Some((policy, priority)) = options.policy {
+        if let Err(err) = set_current_thread_policy(policy, priority) {
+            warn!("Failed to set the scheduling policy of a GC thread to {} ({}): {}", policy, priority, err);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn set_current_thread_affinity(cpus: &[usize]) -> std::io::Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        for &cpu in cpus {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Err(std::io::Error::from_raw_os_error(libc::EINVAL));
+            }
+            libc::CPU_SET(cpu, &mut set);
+        }
+        // pid 0 is the calling thread
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_current_thread_affinity(_cpus: &[usize]) -> std::io::Result<()> {
+    Err(std::io::Error::from(std::io::ErrorKind::Unsupported))
+}
+
+pub fn set_current_thread_policy(policy: c_int, priority: c_int) -> std::io::Result<()> {
+    let mut param: libc::sched_param = unsafe { std::mem::zeroed() };
+    param.sched_priority = priority;
+    match unsafe { libc::pthread_setschedparam(libc::pthread_self(), policy, &param) } {
+        0 => Ok(()),
+        err => Err(std::io::Error::from_raw_os_error(err)),
+    }
+}
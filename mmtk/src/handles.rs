@@ -0,0 +1,99 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use mmtk::util::{Address, ObjectReference};
+
+use crate::abi::Object;
+use crate::scanning::{mmtk_mark_lock_words, RootEdgesClosure, RootsClosure};
+
+/// Number of slots allocated at once when the handle table runs out of free slots.
+const HANDLE_CHUNK_SIZE: usize = 256;
+
+/// Global handles created through `mmtk_handle_new`.
+pub static HANDLES: HandleTable = HandleTable::new();
+
+/// A slab of slots holding references on behalf of native code.
+/// A handle is the address of its slot, which never moves once allocated.
+/// The slots are reported to MMTk as root edges, so the referents stay movable
+/// and the GC updates the slots when it moves them.
+pub struct HandleTable {
+    inner: Mutex<HandleTableInner>,
+}
+
+struct HandleTableInner {
+    chunks: Vec<Box<[AtomicUsize]>>,
+    free: Vec<Address>,
+}
+
+impl HandleTable {
+    pub const fn new() -> Self {
+        Self {
+            inner: Mutex::new(HandleTableInner {
+                chunks: Vec::new(),
+                free: Vec::new(),
+            }),
+        }
+    }
+
+    /// Allocate a slot holding `object` and return its address as the handle.
+    pub fn new_handle(&self, object: ObjectReference) -> Address {
+        debug_assert!(!object.is_null());
+        let mut inner = self.inner.lock().unwrap();
+        if inner.free.is_empty() {
+            let chunk: Box<[AtomicUsize]> = (0..HANDLE_CHUNK_SIZE).map(|_| AtomicUsize::new(0)).collect();
+            // Hand out the slots in address order.
+            for slot in chunk.iter().rev() {
+                inner.free.push(Address::from_ref(slot));
+            }
+            inner.chunks.push(chunk);
+        }
+        let handle = inner.free.pop().unwrap();
+        unsafe { handle.as_ref::<AtomicUsize>() }.store(object.to_raw_address().as_usize(), Ordering::SeqCst);
+        handle
+    }
+
+    /// Read the current referent of `handle`.
+    pub fn get(handle: Address) -> ObjectReference {
+        let value = unsafe { handle.as_ref::<AtomicUsize>() }.load(Ordering::SeqCst);
+        ObjectReference::from_raw_address(unsafe { Address::from_usize(value) })
+    }
+
+    /// Clear `handle` and return its slot to the free list.
+    pub fn free_handle(&self, handle: Address) {
+        let mut inner = self.inner.lock().unwrap();
+        debug_assert!(inner.chunks.iter().any(|chunk| {
+            let start = Address::from_ref(&chunk[0]);
+            handle >= start && handle < start + HANDLE_CHUNK_SIZE * std::mem::size_of::<AtomicUsize>()
+        }), "{} is not a handle", handle);
+        unsafe { handle.as_ref::<AtomicUsize>() }.store(0, Ordering::SeqCst);
+        inner.free.push(handle);
+    }
+
+    /// Call `f` with the address of every slot that currently holds a reference.
+    pub fn for_each_slot(&self, mut f: impl FnMut(Address)) {
+        let inner = self.inner.lock().unwrap();
+        for chunk in inner.chunks.iter() {
+            for slot in chunk.iter() {
+                if slot.load(Ordering::Relaxed) != 0 {
+                    f(Address::from_ref(slot));
+                }
+            }
+        }
+    }
+}
+
+impl Default for HandleTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Report all the handle slots as root edges, and the monitors in the lock words of their
+/// objects as roots.
+pub fn mmtk_scan_handles(edges_closure: &mut RootEdgesClosure, roots_closure: &mut RootsClosure) {
+    HANDLES.for_each_slot(|slot| {
+        let object = unsafe { slot.load::<*mut Object>() };
+        mmtk_mark_lock_words(object, roots_closure);
+        edges_closure.do_work(slot);
+    });
+}
@@ -3,7 +3,6 @@ extern crate mmtk;
 #[macro_use]
 extern crate lazy_static;
 use abi::GCThreadTLS;
-use abi::MutatorThreadNode;
 use abi::Object;
 use abi::word_t;
 use binding::ScalaNativeBinding;
@@ -31,6 +30,17 @@ pub mod scanning;
 pub mod abi;
 pub mod object_scanning;
 pub mod binding;
+pub mod roots;
+pub mod handles;
+pub mod soft_refs;
+pub mod reference_handler;
+pub mod safepoint;
+pub mod mutators;
+pub mod gc_threads;
+#[cfg(unix)]
+pub mod fork;
+#[cfg(feature = "object_pinning")]
+pub mod pin_scopes;
 
 mod edges;
 #[cfg(test)]
@@ -144,6 +154,37 @@ impl MutatorClosure {
     }
 }
 
+/// A closure for reporting extra conservative root ranges of a mutator, such as its
+/// thread-local storage block or alternate and coroutine stacks. `end` is exclusive.
+#[repr(C)]
+pub struct RangesClosure {
+    pub func: extern "C" fn(start: *mut *mut usize, end: *mut *mut usize, data: &SendPtr<libc::c_void>),
+    pub data: SendPtr<libc::c_void>,
+}
+
+impl RangesClosure {
+    fn from_rust_closure<F>(callback: &mut F) -> Self
+    where
+        F: FnMut(*mut *mut usize, *mut *mut usize),
+    {
+        Self {
+            func: Self::call_rust_closure::<F>,
+            data: SendPtr(callback as *mut F as *mut libc::c_void, PhantomData),
+        }
+    }
+
+    extern "C" fn call_rust_closure<F>(
+        start: *mut *mut usize,
+        end: *mut *mut usize,
+        callback_ptr: &SendPtr<libc::c_void>,
+    ) where
+        F: FnMut(*mut *mut usize, *mut *mut usize),
+    {
+        let callback: &mut F = unsafe { &mut *(callback_ptr.0 as *mut F) };
+        callback(start, end);
+    }
+}
+
 /// A closure for reporting root edges.  The C++ code should pass `data` back as the last argument.
 #[repr(C)]
 pub struct EdgesClosure {
@@ -196,12 +237,17 @@ pub struct ObjectTracerWrapper<'a> {
 #[repr(C)]
 pub struct ScalaNativeUpcalls {
     // collection 
+    /// Ask the mutators to stop at their next safepoint. In multithreaded mode, it need not wait
+    /// for them: the binding then waits for each mutator in managed state to call
+    /// `mmtk_safepoint_acknowledge`.
     pub stop_all_mutators: extern "C" fn(
         tls: VMWorkerThread,
     ),
     pub resume_mutators: extern "C" fn(
         tls: VMWorkerThread,
     ),
+    /// Wait for the GC to finish. In multithreaded mode, the binding puts the thread in native
+    /// state before this call, so its stack range must be saved before it allocates.
     pub block_for_gc: extern "C" fn(
         tls: VMMutatorThread
     ),
@@ -210,6 +256,11 @@ pub struct ScalaNativeUpcalls {
         err_kind: AllocationError,
     ),
     pub schedule_finalizer: extern "C" fn(),
+    /// Attach the current thread, the binding's reference handler thread, to the runtime as a mutator.
+    /// Called each time the thread is about to run the handlers.
+    pub attach_reference_handler_thread: extern "C" fn(),
+    /// Detach the reference handler thread from the runtime once it ran the handlers.
+    pub detach_reference_handler_thread: extern "C" fn(),
     
     // abi
     pub get_object_array_id: extern "C" fn() -> i32,
@@ -223,9 +274,10 @@ pub struct ScalaNativeUpcalls {
     // scanning
     pub get_stack_range: extern "C" fn(tls: VMMutatorThread) -> StackRange,
     pub get_regs_range: extern "C" fn(tls: VMMutatorThread) -> RegsRange,
+    /// Report the TLS block and any extra stack segments of a mutator.
+    pub get_extra_ranges: extern "C" fn(tls: VMMutatorThread, closure: RangesClosure),
     pub get_modules: extern "C" fn() -> *mut *mut word_t,
     pub get_modules_size: extern "C" fn() -> i32,
-    pub get_mutator_threads: extern "C" fn() -> *mut MutatorThreadNode,
     /// Scan all the mutators for roots.
     pub scan_roots_in_all_mutator_threads: extern "C" fn(closure: NodesClosure),
     /// Scan one mutator for roots.
@@ -236,15 +288,10 @@ pub struct ScalaNativeUpcalls {
     pub weak_ref_stack_nullify: extern "C" fn(),
     pub weak_ref_stack_call_handlers: extern "C" fn(),
  
-    // active_plan
-    pub get_mutators: extern "C" fn(closure: MutatorClosure),
-    pub is_mutator: extern "C" fn(tls: VMThread) -> bool,
-    pub number_of_mutators: extern "C" fn() -> size_t,
-    pub get_mmtk_mutator: extern "C" fn(tls: VMMutatorThread) -> *mut Mutator<ScalaNative>,
+    // gc threads
     pub init_gc_worker_thread: extern "C" fn(tls: *mut GCThreadTLS, ctx: SendCtxPtr),
     pub get_gc_thread_tls: extern "C" fn() -> *mut GCThreadTLS,
     pub init_synchronizer_thread: extern "C" fn(),
-    pub get_mutator_context_offset: extern "C" fn() -> usize,
 }
 
 pub static mut UPCALLS: *const ScalaNativeUpcalls = null_mut();
@@ -0,0 +1,242 @@
+use std::sync::{Mutex, MutexGuard};
+#[cfg(not(feature = "scalanative_multithreading_enabled"))]
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::thread::{self, ThreadId};
+
+use mmtk::util::opaque_pointer::*;
+use mmtk::util::Address;
+use mmtk::Mutator;
+
+use crate::safepoint::Safepoint;
+use crate::ScalaNative;
+
+/// The mutators bound through `mmtk_bind_mutator` and `mmtk_attach_current_thread`.
+/// `VMActivePlan` and `stop_all_mutators` are implemented against it.
+pub static MUTATORS: MutatorRegistry = MutatorRegistry::new();
+
+/// The only mutator in single-threaded mode, read by `VMActivePlan` without taking a lock.
+/// If the runtime binds several mutators anyway, only the last one is reported to MMTk.
+#[cfg(not(feature = "scalanative_multithreading_enabled"))]
+pub static SINGLE_MUTATOR: AtomicPtr<Mutator<ScalaNative>> = AtomicPtr::new(std::ptr::null_mut());
+
+/// The states of `scalanative_GC_set_mutator_thread_state`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MutatorState {
+    /// Running managed code. The thread stops at the next safepoint when a GC is requested.
+    Managed = 0,
+    /// In a GC-safe region, e.g. a blocking native call. The thread counts as stopped: its
+    /// stack is scanned from the range it saved before entering the region, and it blocks
+    /// when it leaves the region while a GC is in progress.
+    Native = 1,
+}
+
+/// The bounds of a thread stack: `low` is the lowest address, `high` the first address above it.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StackBounds {
+    pub low: Address,
+    pub high: Address,
+}
+
+impl StackBounds {
+    /// The stack of the calling thread, if the platform can tell.
+    #[cfg(target_os = "linux")]
+    pub fn current() -> Option<Self> {
+        unsafe {
+            let mut attr: libc::pthread_attr_t = std::mem::zeroed();
+            if libc::pthread_getattr_np(libc::pthread_self(), &mut attr) != 0 {
+                return None;
+            }
+            let mut addr: *mut libc::c_void = std::ptr::null_mut();
+            let mut size: libc::size_t = 0;
+            let result = libc::pthread_attr_getstack(&attr, &mut addr, &mut size);
+            libc::pthread_attr_destroy(&mut attr);
+            if result != 0 {
+                return None;
+            }
+            let low = Address::from_mut_ptr(addr);
+            Some(Self { low, high: low + size })
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    pub fn current() -> Option<Self> {
+        unsafe {
+            let thread = libc::pthread_self();
+            let high = Address::from_mut_ptr(libc::pthread_get_stackaddr_np(thread));
+            let size = libc::pthread_get_stacksize_np(thread);
+            Some(Self { low: high - size, high })
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    pub fn current() -> Option<Self> {
+        None
+    }
+
+    pub fn contains(&self, addr: Address) -> bool {
+        self.low <= addr && addr < self.high
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MutatorEntry {
+    pub tls: VMMutatorThread,
+    pub mutator: *mut Mutator<ScalaNative>,
+    pub state: MutatorState,
+    /// The stack of the thread, recorded when the thread registered itself.
+    pub stack: Option<StackBounds>,
+    /// The foreign thread that attached itself through `mmtk_attach_current_thread`.
+    pub attached: Option<ThreadId>,
+}
+
+// The entries are only read while the mutators are stopped or by their own thread.
+unsafe impl Send for MutatorEntry {}
+
+pub struct MutatorRegistry {
+    mutators: Mutex<Vec<MutatorEntry>>,
+}
+
+fn key(tls: VMMutatorThread) -> usize {
+    tls.0.0.to_address().as_usize()
+}
+
+impl MutatorRegistry {
+    pub const fn new() -> Self {
+        Self {
+            mutators: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Register a mutator bound by the runtime for one of its threads.
+    pub fn register(&self, tls: VMMutatorThread, mutator: *mut Mutator<ScalaNative>) {
+        self.add(MutatorEntry {
+            tls,
+            mutator,
+            state: MutatorState::Managed,
+            stack: None,
+            attached: None,
+        });
+    }
+
+    /// Register a mutator for the calling thread, which the runtime did not start.
+    pub fn register_current_thread(&self, tls: VMMutatorThread, mutator: *mut Mutator<ScalaNative>) {
+        self.add(MutatorEntry {
+            tls,
+            mutator,
+            state: MutatorState::Managed,
+            stack: StackBounds::current(),
+            attached: Some(thread::current().id()),
+        });
+    }
+
+    fn add(&self, entry: MutatorEntry) {
+        let mut mutators = self.mutators.lock().unwrap();
+        debug_assert!(mutators.iter().all(|other| other.mutator != entry.mutator), "Mutator {:?} is already registered", entry.mutator);
+        #[cfg(not(feature = "scalanative_multithreading_enabled"))]
+        SINGLE_MUTATOR.store(entry.mutator, Ordering::SeqCst);
+        mutators.push(entry);
+    }
+
+    pub fn unregister(&self, mutator: *mut Mutator<ScalaNative>) {
+        let mut mutators = self.mutators.lock().unwrap();
+        mutators.retain(|entry| entry.mutator != mutator);
+        #[cfg(not(feature = "scalanative_multithreading_enabled"))]
+        let _ = SINGLE_MUTATOR.compare_exchange(mutator, std::ptr::null_mut(), Ordering::SeqCst, Ordering::SeqCst);
+    }
+
+    pub fn len(&self) -> usize {
+        self.mutators.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A copy of the entries, in registration order.
+    pub fn entries(&self) -> Vec<MutatorEntry> {
+        self.mutators.lock().unwrap().clone()
+    }
+
+    pub fn entry(&self, tls: VMMutatorThread) -> Option<MutatorEntry> {
+        let mutators = self.mutators.lock().unwrap();
+        mutators.iter().find(|entry| key(entry.tls) == key(tls)).copied()
+    }
+
+    pub fn contains(&self, tls: VMMutatorThread) -> bool {
+        self.entry(tls).is_some()
+    }
+
+    pub fn mutator(&self, tls: VMMutatorThread) -> Option<*mut Mutator<ScalaNative>> {
+        self.entry(tls).map(|entry| entry.mutator)
+    }
+
+    pub fn stack_bounds(&self, mutator: *mut Mutator<ScalaNative>) -> Option<StackBounds> {
+        let mutators = self.mutators.lock().unwrap();
+        mutators.iter().find(|entry| entry.mutator == mutator).and_then(|entry| entry.stack)
+    }
+
+    /// The mutator attached by the calling thread, if any.
+    pub fn attached_to_current_thread(&self) -> Option<*mut Mutator<ScalaNative>> {
+        let current = Some(thread::current().id());
+        let mutators = self.mutators.lock().unwrap();
+        mutators.iter().find(|entry| entry.attached == current).map(|entry| entry.mutator)
+    }
+
+    pub fn state(&self, tls: VMMutatorThread) -> Option<MutatorState> {
+        self.entry(tls).map(|entry| entry.state)
+    }
+
+    /// The mutators in managed state, which a GC must wait for.
+    pub fn managed(&self) -> Vec<VMMutatorThread> {
+        let mutators = self.mutators.lock().unwrap();
+        mutators.iter().filter(|entry| entry.state == MutatorState::Managed).map(|entry| entry.tls).collect()
+    }
+
+    pub fn is_native(&self, tls: VMMutatorThread) -> bool {
+        self.state(tls) == Some(MutatorState::Native)
+    }
+
+    fn set_state(&self, tls: VMMutatorThread, state: MutatorState) {
+        let mut mutators = self.mutators.lock().unwrap();
+        let entry = mutators.iter_mut().find(|entry| key(entry.tls) == key(tls));
+        entry.unwrap_or_else(|| panic!("Mutator {:?} is not bound", tls)).state = state;
+    }
+
+    pub fn prepare_fork(&'static self) -> MutatorRegistryForkGuard {
+        MutatorRegistryForkGuard(self.mutators.lock().unwrap())
+    }
+
+    /// The mutator `tls` enters a GC-safe region. The runtime must have saved its stack range.
+    pub fn enter_native(&self, tls: VMMutatorThread, safepoint: &Safepoint) {
+        self.set_state(tls, MutatorState::Native);
+        // A GC waiting for this thread does not need to wait any longer.
+        safepoint.acknowledge(tls);
+    }
+
+    /// The mutator `tls` leaves its GC-safe region, after any GC in progress.
+    /// The state changes under the lock of the handshake, so a GC cannot miss the thread:
+    /// see `Safepoint` for the lock order.
+    pub fn exit_native(&self, tls: VMMutatorThread, safepoint: &Safepoint) {
+        safepoint.when_running(|| self.set_state(tls, MutatorState::Managed));
+    }
+}
+
+/// Keeps the registry from changing while the process forks. See `MutatorRegistry::prepare_fork`.
+pub struct MutatorRegistryForkGuard(MutexGuard<'static, Vec<MutatorEntry>>);
+
+impl MutatorRegistryForkGuard {
+    /// In the child, only the forking thread exists. The foreign threads attached by the binding
+    /// are dropped here. The runtime destroys the mutators of its own threads that did not survive.
+    pub fn reset_in_child(mut self) {
+        let current = thread::current().id();
+        self.0.retain(|entry| entry.attached.map_or(true, |thread| thread == current));
+    }
+}
+
+impl Default for MutatorRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
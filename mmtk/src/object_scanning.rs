@@ -3,9 +3,18 @@ use mmtk::memory_manager::is_mmtk_object;
 use mmtk::vm::edge_shape::SimpleEdge;
 use mmtk::{vm::{EdgeVisitor, edge_shape::Edge}, util::{ObjectReference, VMWorkerThread, Address}};
 use crate::{abi::*, edges::ScalaNativeEdge, UPCALLS};
-use crate::scanning::{is_word_in_heap, WEAK_REF_STACK, ObjectSendPtr};
+use crate::scanning::{is_ptr_aligned, is_word_in_heap, push_ephemeron, push_weak_reference};
+#[cfg(feature = "object_pinning")]
+use std::sync::atomic::Ordering;
+#[cfg(feature = "object_pinning")]
+use mmtk::memory_manager;
+#[cfg(feature = "object_pinning")]
+use crate::ScalaNative;
 
 pub const LAST_FIELD_OFFSET: i64 = -1;
+/// The forwarding bits live in the lowest two bits of the rtti word. See `VMObjectModel`.
+#[cfg(feature = "object_pinning")]
+const FORWARDING_BITS_MASK: usize = 0b11;
 lazy_static! {
 	static ref __OBJECT_ARRAY_ID: i32 = unsafe {
 		((*UPCALLS).get_object_array_id)()
@@ -103,6 +112,33 @@ impl<'a, ES: Edge> EdgeVisitor<ES> for ClosureWrapper<'a, ES> {
 	}
 }
 
+/// Pin `object` until the end of the current GC. See `ScalaNativeBinding::unpin_pinned_objects`.
+/// Objects that are already (being) forwarded no longer carry their rtti and are skipped:
+/// tracing them yields their new address anyway.
+#[cfg(feature = "object_pinning")]
+fn pin_during_gc(object: *mut Object) {
+	let rtti = unsafe { (*object).rtti } as usize;
+	if rtti & FORWARDING_BITS_MASK != 0 {
+		return;
+	}
+	let object = ObjectReference::from_raw_address(Address::from_mut_ptr(object));
+	if memory_manager::pin_object::<ScalaNative>(object) {
+		crate::binding().pinned_objects.lock().unwrap().push(object);
+	}
+}
+
+/// Pin `object` if it is a stack chunk and the runtime asked for stack chunks to be pinned.
+#[inline]
+fn pin_if_stack_chunk(_object: *mut Object) {
+	#[cfg(feature = "object_pinning")]
+	unsafe {
+		let rtti = (*_object).rtti as usize;
+		if PIN_STACK_CHUNKS.load(Ordering::Relaxed) && rtti & FORWARDING_BITS_MASK == 0 && (*_object).is_stack_chunk() {
+			pin_during_gc(_object);
+		}
+	}
+}
+
 #[inline]
 pub fn mmtk_scan_field(
 	edge: *mut Field_t,
@@ -118,10 +154,11 @@ pub fn mmtk_scan_field(
 			debug_assert!(!(*object).rtti.is_null());
 			mmtk_scan_lock_words(object, closure);
 			if (*object).is_weak_reference() {
-					WEAK_REF_STACK.lock().unwrap().push(ObjectSendPtr(object));
+					push_weak_reference(object);
 			}
 
 			debug_assert!((*object).size() != 0);
+			pin_if_stack_chunk(object);
 			// Create the work packets here
 			closure.visit_edge(simple_edge);
 		}
@@ -136,6 +173,7 @@ pub fn mmtk_scan_field_and_trace_edges(
 ) {
 	let field_addr = Address::from_mut_ptr(field);
 	if is_mmtk_object(field_addr) {
+		pin_if_stack_chunk(field as *mut Object);
 		unsafe {
 			let traced = closure.trace_object(ObjectReference::from_raw_address(field_addr));
 			let field_addr = Address::from_mut_ptr(traced.value() as *mut usize);
@@ -144,7 +182,7 @@ pub fn mmtk_scan_field_and_trace_edges(
 			debug_assert!(!(*object).rtti.is_null(), "{:p}'s rtti is null: {:p}, lock_word: {:p}", object, (*object).rtti, (*object).lock_word);
 			mmtk_scan_lock_words_and_trace_edges(object, closure);
 			if (*object).is_weak_reference() {
-					WEAK_REF_STACK.lock().unwrap().push(ObjectSendPtr(object));
+					push_weak_reference(object);
 			}
 
 			debug_assert!((*object).size() != 0, "{:p}'s size is 0", object);
@@ -215,13 +253,17 @@ pub fn mmtk_scan_lock_words_and_trace_edges(
 
 impl ObjIterate for Object {
 	fn obj_iterate(&self, closure: &mut impl EdgeVisitor<ScalaNativeEdge>) {
+		let weak_fields = self.weak_fields();
+		if let WeakFields::Ephemeron { .. } = weak_fields {
+			push_ephemeron(self as *const _ as *mut Object);
+		}
 		let ptr_map: *mut i64 = unsafe { (*(self.rtti)).ref_map_struct };
 		let mut i = 0;
 		let fields = self.get_fields();
 		unsafe {
 			while *ptr_map.offset(i) != LAST_FIELD_OFFSET {
 				let offset = *ptr_map.offset(i);
-				if self.is_referant_of_weak_reference(offset.try_into().unwrap()) {
+				if weak_fields.contains(offset.try_into().unwrap()) {
 					i += 1;
 					continue
 				}
@@ -237,13 +279,17 @@ impl ObjIterate for Object {
 
 	
 	fn obj_iterate_and_trace_edges(&self, closure: &mut impl mmtk::vm::ObjectTracer) {
+		let weak_fields = self.weak_fields();
+		if let WeakFields::Ephemeron { .. } = weak_fields {
+			push_ephemeron(self as *const _ as *mut Object);
+		}
 		let ptr_map: *mut i64 = unsafe { (*(self.rtti)).ref_map_struct };
 		let mut i = 0;
 		let fields = self.get_fields();
 		unsafe {
 			while *ptr_map.offset(i) != LAST_FIELD_OFFSET {
 				let offset = *ptr_map.offset(i);
-				if self.is_referant_of_weak_reference(offset.try_into().unwrap()) {
+				if weak_fields.contains(offset.try_into().unwrap()) {
 					i += 1;
 					continue
 				}
@@ -294,11 +340,47 @@ impl ObjIterate for ArrayHeader {
 	}
 }
 
+impl StackChunk {
+	/// Call `f` with the slot and value of every word of the chunk that holds the address of
+	/// an object. With `object_pinning`, those objects are pinned first, like the objects
+	/// found on thread stacks, so tracing them leaves the words as they are.
+	fn for_each_ambiguous_reference(&self, mut f: impl FnMut(*mut Field_t, Field_t)) {
+		let words = self.data.as_ptr() as *mut Field_t;
+		unsafe {
+			for i in 0..self.words().len() {
+				let edge = words.add(i);
+				let field = *edge;
+				if is_word_in_heap(field) && is_ptr_aligned(field) && is_mmtk_object(Address::from_mut_ptr(field)) {
+					#[cfg(feature = "object_pinning")]
+					pin_during_gc(field as *mut Object);
+					f(edge, field);
+				}
+			}
+		}
+	}
+}
+
+// The words of a stack chunk are ambiguous, and are only scanned when the chunk is traced,
+// so a dead continuation keeps nothing alive. Their referents are pinned with `object_pinning`.
+// Without it they are traced like fields: they may be moved and the words updated.
+impl ObjIterate for StackChunk {
+	fn obj_iterate(&self, closure: &mut impl EdgeVisitor<ScalaNativeEdge>) {
+		self.for_each_ambiguous_reference(|edge, field| mmtk_scan_field(edge, field, closure));
+	}
+
+	fn obj_iterate_and_trace_edges(&self, closure: &mut impl mmtk::vm::ObjectTracer) {
+		self.for_each_ambiguous_reference(|edge, field| mmtk_scan_field_and_trace_edges(edge, field, closure));
+	}
+}
+
 fn obj_iterate(obj: Obj, closure: &mut impl EdgeVisitor<ScalaNativeEdge>) {
 	match obj.is_array() {
 		true => {
 			unsafe { obj.as_array_object().obj_iterate(closure) }
 		},
+		false if obj.is_stack_chunk() => {
+			unsafe { obj.as_stack_chunk().obj_iterate(closure) }
+		},
 		false => {
 			obj.obj_iterate(closure)
 		},
@@ -310,6 +392,9 @@ fn obj_iterate_and_trace_edges(obj: Obj, closure: &mut impl mmtk::vm::ObjectTrac
 		true => {
 			unsafe { obj.as_array_object().obj_iterate_and_trace_edges(closure) }
 		},
+		false if obj.is_stack_chunk() => {
+			unsafe { obj.as_stack_chunk().obj_iterate_and_trace_edges(closure) }
+		},
 		false => {
 			obj.obj_iterate_and_trace_edges(closure)
 		},
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use mmtk::memory_manager;
+use mmtk::util::ObjectReference;
+use mmtk::Mutator;
+use mmtk::MutatorContext;
+
+use crate::mutators::MUTATORS;
+use crate::ScalaNative;
+
+lazy_static! {
+    pub static ref PIN_SCOPES: PinScopes = PinScopes::new();
+}
+
+/// Pin scopes opened by mutators around native calls, through `mmtk_pin_scope_enter` and
+/// `mmtk_pin_scope_exit`. Each mutator has a stack of scopes. An object pinned in a scope
+/// is unpinned when that scope exits. Objects that were already pinned, by an enclosing
+/// scope or by `mmtk_pin_object`, are left to whoever pinned them.
+///
+/// Objects must be pinned in managed state, before the mutator enters native state with
+/// `mmtk_enter_native`, and scopes exited after `mmtk_exit_native`. A managed mutator is
+/// stopped while a GC traces the objects, whereas a native one keeps running and could pin
+/// an object the GC is already moving.
+pub struct PinScopes {
+    scopes: Mutex<HashMap<usize, Vec<Vec<ObjectReference>>>>,
+}
+
+impl PinScopes {
+    pub fn new() -> Self {
+        Self {
+            scopes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn enter(&self, mutator: *mut Mutator<ScalaNative>) {
+        let mut scopes = self.scopes.lock().unwrap();
+        scopes.entry(mutator as usize).or_default().push(Vec::new());
+    }
+
+    /// Pin `object` until the innermost scope of `mutator` exits.
+    /// Returns false if it was already pinned.
+    pub fn pin(&self, mutator: *mut Mutator<ScalaNative>, object: ObjectReference) -> bool {
+        debug_assert!(
+            !MUTATORS.is_native(unsafe { (*mutator).get_tls() }),
+            "Pinning {:?} in native state",
+            object
+        );
+        let mut scopes = self.scopes.lock().unwrap();
+        let scope = scopes
+            .get_mut(&(mutator as usize))
+            .and_then(|stack| stack.last_mut())
+            .expect("Pinning an object outside of a pin scope");
+        let pinned = memory_manager::pin_object::<ScalaNative>(object);
+        if pinned {
+            scope.push(object);
+        }
+        pinned
+    }
+
+    /// Exit the innermost scope of `mutator`, unpinning the objects pinned in it.
+    pub fn exit(&self, mutator: *mut Mutator<ScalaNative>) {
+        let mut scopes = self.scopes.lock().unwrap();
+        let stack = scopes.get_mut(&(mutator as usize)).expect("No pin scope to exit");
+        let scope = stack.pop().expect("No pin scope to exit");
+        if stack.is_empty() {
+            scopes.remove(&(mutator as usize));
+        }
+        unpin_all(scope);
+    }
+
+    /// Exit all the scopes of `mutator`, whose thread is exiting.
+    pub fn release_mutator(&self, mutator: *mut Mutator<ScalaNative>) {
+        let stack = self.scopes.lock().unwrap().remove(&(mutator as usize));
+        for scope in stack.into_iter().flatten() {
+            unpin_all(scope);
+        }
+    }
+
+    /// The number of open scopes of `mutator`.
+    pub fn depth(&self, mutator: *mut Mutator<ScalaNative>) -> usize {
+        self.scopes.lock().unwrap().get(&(mutator as usize)).map_or(0, Vec::len)
+    }
+}
+
+impl Default for PinScopes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn unpin_all(objects: Vec<ObjectReference>) {
+    for object in objects {
+        let result = memory_manager::unpin_object::<ScalaNative>(object);
+        debug_assert!(result, "{:?} was unpinned outside of its pin scope", object);
+    }
+}
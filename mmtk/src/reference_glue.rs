@@ -1,20 +1,38 @@
+use log::debug;
 use mmtk::vm::ReferenceGlue;
-use mmtk::util::ObjectReference;
+use mmtk::util::{Address, ObjectReference};
 use mmtk::util::opaque_pointer::VMWorkerThread;
 use crate::ScalaNative;
+use crate::abi::{Obj, WEAK_REF_FIELD_OFFSET};
 
 pub struct VMReferenceGlue {}
 
+/// The slot of the referent in a Scala Native `java.lang.ref.Reference`.
+fn referent_slot(reference: ObjectReference) -> Address {
+    debug_assert!(Obj::from(reference).is_weak_reference(), "{:?} is not a reference object", reference);
+    let edge = unsafe { Obj::from(reference).get_fields().offset(*WEAK_REF_FIELD_OFFSET as isize) };
+    Address::from_mut_ptr(edge)
+}
+
+pub(crate) fn referent_of(reference: ObjectReference) -> ObjectReference {
+    unsafe { referent_slot(reference).load::<ObjectReference>() }
+}
+
+pub(crate) fn set_referent_of(reference: ObjectReference, referent: ObjectReference) {
+    unsafe { referent_slot(reference).store(referent) }
+}
+
 impl ReferenceGlue<ScalaNative> for VMReferenceGlue {
     type FinalizableType = ObjectReference;
 
-    fn set_referent(_reference: ObjectReference, _referent: ObjectReference) {
-        unimplemented!()
+    fn set_referent(reference: ObjectReference, referent: ObjectReference) {
+        set_referent_of(reference, referent)
     }
-    fn get_referent(_object: ObjectReference) -> ObjectReference {
-        unimplemented!()
+    fn get_referent(object: ObjectReference) -> ObjectReference {
+        referent_of(object)
     }
-    fn enqueue_references(_references: &[ObjectReference], _tls: VMWorkerThread) {
-        unimplemented!()
+    fn enqueue_references(references: &[ObjectReference], _tls: VMWorkerThread) {
+        debug!("{} references cleared by the reference processor", references.len());
+        crate::scanning::record_cleared_references(references);
     }
 }
@@ -0,0 +1,153 @@
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+
+use log::debug;
+
+use crate::scanning::{mmtk_enqueue_cleared_references, mmtk_weak_ref_stack_call_handlers};
+use crate::UPCALLS;
+
+pub static REFERENCE_HANDLER: ReferenceHandler = ReferenceHandler::new();
+
+/// The "Reference Handler" thread, owned by the binding.
+///
+/// After each GC, once the mutators are resumed, it runs the weak reference handler, hands the
+/// cleared references to the reference queue handler and schedules the finalizers. These call
+/// back into the runtime, which may allocate or take locks, so they must not run while the
+/// mutators are stopped. The thread attaches itself to the runtime, as a mutator, only while it
+/// runs the handlers: waiting for the next GC detached, it never holds up a stop-the-world request.
+/// It is started by the first GC and runs until `shutdown`.
+pub struct ReferenceHandler {
+    state: Mutex<HandlerState>,
+    condvar: Condvar,
+}
+
+struct HandlerState {
+    thread: Option<JoinHandle<()>>,
+    /// A GC finished since the references were last handled.
+    pending: bool,
+    /// A GC found objects to finalize since the finalizers were last scheduled.
+    finalization: bool,
+    /// The thread is running the handlers.
+    running: bool,
+    shutdown: bool,
+}
+
+impl ReferenceHandler {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(HandlerState {
+                thread: None,
+                pending: false,
+                finalization: false,
+                running: false,
+                shutdown: false,
+            }),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Ask the thread to run the handlers, starting it if needed. Called when a GC resumes the mutators.
+    pub fn wake(&'static self, finalization: bool) {
+        let mut state = self.state.lock().unwrap();
+        if state.shutdown {
+            return;
+        }
+        state.pending = true;
+        state.finalization |= finalization;
+        if state.thread.is_none() {
+            let thread = thread::Builder::new()
+                .name("Reference Handler".to_string())
+                .spawn(move || self.run())
+                .unwrap();
+            state.thread = Some(thread);
+        }
+        self.condvar.notify_all();
+    }
+
+    /// Block until the thread handled every GC it was woken for.
+    pub fn wait_until_idle(&self) {
+        let mut state = self.state.lock().unwrap();
+        while state.thread.is_some() && (state.pending || state.running) {
+            state = self.condvar.wait(state).unwrap();
+        }
+    }
+
+    /// Stop the thread once it handled the pending references, and wait for it to exit.
+    /// The handlers are not run anymore after this.
+    pub fn shutdown(&self) {
+        let thread = {
+            let mut state = self.state.lock().unwrap();
+            state.shutdown = true;
+            self.condvar.notify_all();
+            state.thread.take()
+        };
+        if let Some(thread) = thread {
+            if thread.thread().id() != thread::current().id() {
+                thread.join().unwrap();
+            }
+        }
+    }
+
+    /// Keep the thread from taking the state while the process forks.
+    pub fn prepare_fork(&'static self) -> ReferenceHandlerForkGuard {
+        ReferenceHandlerForkGuard(self.state.lock().unwrap())
+    }
+
+    fn run(&self) {
+        debug!("Hello! This is the Reference Handler thread running!");
+        loop {
+            let finalization = {
+                let mut state = self.state.lock().unwrap();
+                while !state.pending && !state.shutdown {
+                    state = self.condvar.wait(state).unwrap();
+                }
+                if !state.pending {
+                    break;
+                }
+                state.pending = false;
+                state.running = true;
+                std::mem::take(&mut state.finalization)
+            };
+            unsafe { ((*UPCALLS).attach_reference_handler_thread)() };
+            handle_references(finalization);
+            unsafe { ((*UPCALLS).detach_reference_handler_thread)() };
+            let mut state = self.state.lock().unwrap();
+            state.running = false;
+            self.condvar.notify_all();
+        }
+        debug!("The Reference Handler thread is quitting");
+        // Wake the threads waiting for the handlers to run, as they never will again.
+        self.condvar.notify_all();
+    }
+}
+
+/// Keeps the reference handler state while the process forks. See `ReferenceHandler::prepare_fork`.
+pub struct ReferenceHandlerForkGuard(MutexGuard<'static, HandlerState>);
+
+impl ReferenceHandlerForkGuard {
+    /// In the child, the thread does not exist anymore. The next GC starts a new one, which
+    /// also handles the references pending at the time of the fork.
+    pub fn reset_in_child(mut self) {
+        // Neither join nor detach a thread of the parent.
+        std::mem::forget(self.0.thread.take());
+        self.0.running = false;
+        self.0.shutdown = false;
+    }
+}
+
+/// Run the weak reference handlers, hand the cleared references to the reference queue handler
+/// and schedule the finalizers if `finalization`. Called by the reference handler thread, or by
+/// the mutator after a GC in single-threaded mode.
+pub(crate) fn handle_references(finalization: bool) {
+    mmtk_weak_ref_stack_call_handlers();
+    mmtk_enqueue_cleared_references();
+    if finalization {
+        unsafe { ((*UPCALLS).schedule_finalizer)() };
+    }
+}
+
+impl Default for ReferenceHandler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+use std::mem;
+use std::sync::RwLock;
+
+use crate::scanning::{mmtk_mark_range, RootsClosure};
+
+/// Address ranges registered by native code through `mmtk_add_roots`.
+/// This is the equivalent of Scala Native's `scalanative_GC_add_roots`.
+pub static ROOT_RANGES: RootRanges = RootRanges::new();
+
+/// A set of half-open `[start, end)` address ranges, keyed by their start address.
+/// Ranges are registered and removed by mutators while GC workers scan them,
+/// so the set is guarded by a `RwLock` and only briefly locked for writing.
+pub struct RootRanges {
+    ranges: RwLock<BTreeMap<usize, usize>>,
+}
+
+impl RootRanges {
+    pub const fn new() -> Self {
+        Self {
+            ranges: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    /// Register `[start, end)` as a root range. The range is shrunk to word boundaries.
+    /// Adding a range with the same start address as an existing one extends it.
+    pub fn add(&self, start: usize, end: usize) {
+        let word = mem::size_of::<usize>();
+        let start = (start + word - 1) & !(word - 1);
+        let end = end & !(word - 1);
+        if start >= end {
+            return;
+        }
+        let mut ranges = self.ranges.write().unwrap();
+        let entry = ranges.entry(start).or_insert(end);
+        if *entry < end {
+            *entry = end;
+        }
+    }
+
+    /// Unregister every range that lies entirely within `[start, end)`.
+    pub fn remove(&self, start: usize, end: usize) {
+        let mut ranges = self.ranges.write().unwrap();
+        ranges.retain(|&s, &mut e| s < start || e > end);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.read().unwrap().is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.read().unwrap().len()
+    }
+
+    /// Call `f` for every registered `[start, end)` range in address order.
+    pub fn for_each(&self, mut f: impl FnMut(usize, usize)) {
+        let ranges = self.ranges.read().unwrap();
+        for (&start, &end) in ranges.iter() {
+            f(start, end);
+        }
+    }
+}
+
+impl Default for RootRanges {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Conservatively scan all the registered root ranges.
+pub unsafe fn mmtk_mark_root_ranges(roots_closure: &mut RootsClosure) {
+    ROOT_RANGES.for_each(|start, end| {
+        // `mmtk_mark_range` includes its upper bound, so stop at the last word of the range.
+        let from = start as *mut *mut usize;
+        let to = (end - mem::size_of::<usize>()) as *mut *mut usize;
+        mmtk_mark_range(from, to, roots_closure);
+    });
+}
@@ -0,0 +1,385 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use log::debug;
+use mmtk::util::opaque_pointer::*;
+
+/// The handshake between the GC and the mutators, served by the "MMTk Synchronizer Thread".
+pub static SAFEPOINT: Safepoint = Safepoint::new();
+
+/// How long the GC waits for the mutators to stop or resume before warning about it.
+/// When the mutators are slow to stop, the warning names the threads that did not acknowledge.
+pub static SAFEPOINT_TIMEOUT_MS: AtomicU64 = AtomicU64::new(10_000);
+
+/// Bucket `i > 0` of the time-to-safepoint histogram counts the stops that took
+/// `[2^(i-1), 2^i)` microseconds, bucket 0 those under a microsecond. The last bucket is open-ended.
+pub const SAFEPOINT_HISTOGRAM_BUCKETS: usize = 32;
+
+fn histogram_bucket(duration: Duration) -> usize {
+    let micros = duration.as_micros().min(u64::MAX as u128) as u64;
+    ((u64::BITS - micros.leading_zeros()) as usize).min(SAFEPOINT_HISTOGRAM_BUCKETS - 1)
+}
+
+/// Aggregated timings of the safepoints, readable through `mmtk_get_safepoint_stats`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SafepointStats {
+    /// The number of times the mutators were stopped.
+    pub count: u64,
+    /// Time-to-safepoint: from the stop request until every mutator is stopped.
+    pub total_ns: u64,
+    pub max_ns: u64,
+    pub last_ns: u64,
+    /// From the stop request of the last GC until the mutators were resumed.
+    pub last_pause_ns: u64,
+}
+
+pub fn safepoint_timeout() -> Duration {
+    Duration::from_millis(SAFEPOINT_TIMEOUT_MS.load(Ordering::Relaxed))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// The mutators are running.
+    Running,
+    /// A GC asked for the mutators to stop.
+    StopRequested,
+    /// Every mutator acknowledged the stop. The GC may scan them.
+    Stopped,
+    /// The GC is done and asked for the mutators to resume.
+    ResumeRequested,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum HandshakeError {
+    /// No synchronizer thread serves the handshake.
+    NotServed,
+    /// The synchronizer thread was shut down. See `serve_in_place`.
+    ShutDown,
+    /// The request is not valid in the current phase.
+    InvalidPhase(Phase),
+    /// The mutators did not reach the phase in time. The request is still pending.
+    Timeout(Phase, Duration),
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::NotServed => write!(f, "no synchronizer thread serves the safepoint"),
+            HandshakeError::ShutDown => write!(f, "the synchronizer thread was shut down"),
+            HandshakeError::InvalidPhase(phase) => write!(f, "invalid request in phase {:?}", phase),
+            HandshakeError::Timeout(phase, timeout) => write!(f, "{:?} not reached after {:?}", phase, timeout),
+        }
+    }
+}
+
+/// A GC request to stop the mutators, their acknowledgement, and their resumption.
+///
+/// The runtime stops and resumes the mutators by taking and releasing a lock, which must
+/// happen on the same thread, while MMTk may stop and resume the mutators from different
+/// GC workers. So the upcalls are made by a single synchronizer thread running `serve`,
+/// and the GC workers hand it requests and wait for it to reach the requested phase.
+///
+/// The stop upcall only asks the mutators to stop. The handshake then waits for each mutator
+/// in managed state to acknowledge; the threads in native state are not waited for.
+///
+/// Lock order: the lock of the handshake is taken before the lock of the mutator registry,
+/// never the other way around. `serve` lists the managed mutators, and
+/// `MutatorRegistry::exit_native` changes the state of a mutator, while holding it.
+pub struct Safepoint {
+    state: Mutex<SafepointState>,
+    condvar: Condvar,
+}
+
+struct SafepointState {
+    phase: Phase,
+    /// The GC worker that made the pending request.
+    requester: VMWorkerThread,
+    served: bool,
+    shutdown: bool,
+    /// When the mutators were last asked to stop.
+    requested_at: Option<Instant>,
+    /// The mutators that reached the safepoint since, and when.
+    acknowledged: Vec<(usize, Instant)>,
+    stats: SafepointStats,
+    histogram: [u64; SAFEPOINT_HISTOGRAM_BUCKETS],
+}
+
+impl Safepoint {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(SafepointState {
+                phase: Phase::Running,
+                requester: VMWorkerThread(VMThread::UNINITIALIZED),
+                served: false,
+                shutdown: false,
+                requested_at: None,
+                acknowledged: Vec::new(),
+                stats: SafepointStats {
+                    count: 0,
+                    total_ns: 0,
+                    max_ns: 0,
+                    last_ns: 0,
+                    last_pause_ns: 0,
+                },
+                histogram: [0; SAFEPOINT_HISTOGRAM_BUCKETS],
+            }),
+            condvar: Condvar::new(),
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.state.lock().unwrap().phase
+    }
+
+    /// Ask the synchronizer thread to stop the mutators. Use `wait_for(Phase::Stopped)`
+    /// before touching them.
+    pub fn request_stop(&self, tls: VMWorkerThread) -> Result<(), HandshakeError> {
+        self.request(tls, Phase::Running, Phase::StopRequested)
+    }
+
+    /// Ask the synchronizer thread to resume the mutators once the GC is done with them.
+    pub fn request_resume(&self, tls: VMWorkerThread) -> Result<(), HandshakeError> {
+        self.request(tls, Phase::Stopped, Phase::ResumeRequested)
+    }
+
+    fn request(&self, tls: VMWorkerThread, expected: Phase, requested: Phase) -> Result<(), HandshakeError> {
+        let mut state = self.state.lock().unwrap();
+        if state.shutdown {
+            return Err(HandshakeError::ShutDown);
+        }
+        if !state.served {
+            return Err(HandshakeError::NotServed);
+        }
+        state.begin(tls, expected, requested)?;
+        self.condvar.notify_all();
+        Ok(())
+    }
+
+    /// Serve a request on the calling GC thread, once the synchronizer thread was shut down.
+    /// A collection requested before `mmtk_stop_collection` disabled collection may still stop the
+    /// mutators afterwards: the upcalls are then made by the GC threads themselves.
+    pub fn serve_in_place(
+        &self,
+        tls: VMWorkerThread,
+        requested: Phase,
+        mut stop: impl FnMut(VMWorkerThread),
+        mut resume: impl FnMut(VMWorkerThread),
+        mut managed: impl FnMut() -> Vec<VMMutatorThread>,
+    ) -> Result<(), HandshakeError> {
+        let mut state = self.state.lock().unwrap();
+        if !state.shutdown {
+            return Err(HandshakeError::InvalidPhase(state.phase));
+        }
+        // The synchronizer thread may still be on its way out.
+        while state.served {
+            state = self.condvar.wait(state).unwrap();
+        }
+        let expected = match requested {
+            Phase::StopRequested => Phase::Running,
+            _ => Phase::Stopped,
+        };
+        state.begin(tls, expected, requested)?;
+        drop(self.handle(state, &mut stop, &mut resume, &mut managed));
+        Ok(())
+    }
+
+    /// Record that the mutator `tls` reached the safepoint. Called by each mutator when it stops.
+    pub fn acknowledge(&self, tls: VMMutatorThread) {
+        let mut state = self.state.lock().unwrap();
+        let tls = tls.0.0.to_address().as_usize();
+        if state.phase == Phase::StopRequested && !state.acknowledged.iter().any(|&(t, _)| t == tls) {
+            state.acknowledged.push((tls, Instant::now()));
+            self.condvar.notify_all();
+        }
+    }
+
+    pub fn is_acknowledged(&self, tls: VMMutatorThread) -> bool {
+        self.state.lock().unwrap().is_acknowledged(tls)
+    }
+
+    /// The time each mutator took to reach the last safepoint, in the order they reached it.
+    pub fn acknowledgements(&self) -> Vec<(usize, Duration)> {
+        let state = self.state.lock().unwrap();
+        let Some(requested_at) = state.requested_at else {
+            return Vec::new();
+        };
+        state.acknowledged.iter().map(|&(tls, at)| (tls, at - requested_at)).collect()
+    }
+
+    pub fn stats(&self) -> SafepointStats {
+        self.state.lock().unwrap().stats
+    }
+
+    pub fn histogram(&self) -> [u64; SAFEPOINT_HISTOGRAM_BUCKETS] {
+        self.state.lock().unwrap().histogram
+    }
+
+    /// Block while the mutators are stopped, then run `f` before another stop can be requested.
+    /// Used by threads leaving a GC-safe region, which must not run while a GC is in progress.
+    pub fn when_running<R>(&self, f: impl FnOnce() -> R) -> R {
+        let mut state = self.state.lock().unwrap();
+        while state.phase != Phase::Running {
+            state = self.condvar.wait(state).unwrap();
+        }
+        let result = f();
+        drop(state);
+        result
+    }
+
+    /// Wait for a collection in progress to resume the mutators, then hold the handshake until
+    /// the guard is dropped, so the process never forks with the mutators stopped.
+    pub fn prepare_fork(&'static self) -> SafepointForkGuard {
+        let mut state = self.state.lock().unwrap();
+        while state.served && state.phase != Phase::Running {
+            state = self.condvar.wait(state).unwrap();
+        }
+        SafepointForkGuard(state)
+    }
+
+    /// Wait until the handshake reaches `phase`, for at most `timeout`.
+    pub fn wait_for(&self, phase: Phase, timeout: Duration) -> Result<(), HandshakeError> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock().unwrap();
+        while state.phase != phase {
+            if !state.served {
+                return Err(HandshakeError::NotServed);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(HandshakeError::Timeout(phase, timeout));
+            }
+            state = self.condvar.wait_timeout(state, deadline - now).unwrap().0;
+        }
+        Ok(())
+    }
+
+    /// Serve the requests on the current thread until `shutdown` is called.
+    /// After `stop`, the mutators are stopped once every mutator listed by `managed` acknowledged.
+    /// `managed` is called with the lock of the handshake held.
+    pub fn serve(
+        &self,
+        mut stop: impl FnMut(VMWorkerThread),
+        mut resume: impl FnMut(VMWorkerThread),
+        mut managed: impl FnMut() -> Vec<VMMutatorThread>,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        debug_assert!(!state.served, "The safepoint is already served");
+        state.served = true;
+        loop {
+            while !state.shutdown && !matches!(state.phase, Phase::StopRequested | Phase::ResumeRequested) {
+                state = self.condvar.wait(state).unwrap();
+            }
+            if state.shutdown {
+                break;
+            }
+            state = self.handle(state, &mut stop, &mut resume, &mut managed);
+        }
+        state.served = false;
+        self.condvar.notify_all();
+    }
+
+    /// Make the upcall for the pending request, with the lock released, and wait for the
+    /// mutators to reach the requested phase.
+    fn handle<'s>(
+        &'s self,
+        state: MutexGuard<'s, SafepointState>,
+        stop: &mut impl FnMut(VMWorkerThread),
+        resume: &mut impl FnMut(VMWorkerThread),
+        managed: &mut impl FnMut() -> Vec<VMMutatorThread>,
+    ) -> MutexGuard<'s, SafepointState> {
+        let (phase, requester) = (state.phase, state.requester);
+        drop(state);
+        let reached = match phase {
+            Phase::StopRequested => {
+                stop(requester);
+                Phase::Stopped
+            }
+            _ => {
+                resume(requester);
+                Phase::Running
+            }
+        };
+        let mut state = self.state.lock().unwrap();
+        if reached == Phase::Stopped {
+            // A thread entering native state acknowledges, which wakes us up to check again.
+            while !managed().into_iter().all(|tls| state.is_acknowledged(tls)) {
+                state = self.condvar.wait(state).unwrap();
+            }
+        }
+        state.phase = reached;
+        state.record(reached);
+        self.condvar.notify_all();
+        state
+    }
+
+    /// Make `serve` return. If the mutators are stopped, waits until a collection resumes them,
+    /// so no thread is left blocked at its safepoint. Later requests fail with `ShutDown`.
+    pub fn shutdown(&self) {
+        let mut state = self.state.lock().unwrap();
+        while state.served && state.phase != Phase::Running {
+            state = self.condvar.wait(state).unwrap();
+        }
+        state.shutdown = true;
+        self.condvar.notify_all();
+    }
+}
+
+/// Keeps the GC from stopping the mutators while the process forks. See `Safepoint::prepare_fork`.
+pub struct SafepointForkGuard(MutexGuard<'static, SafepointState>);
+
+impl SafepointForkGuard {
+    /// In the child, the synchronizer thread does not exist anymore, so nothing serves the requests.
+    pub fn reset_in_child(mut self) {
+        self.0.served = false;
+        self.0.shutdown = false;
+        self.0.requested_at = None;
+        self.0.acknowledged.clear();
+    }
+}
+
+impl SafepointState {
+    fn begin(&mut self, tls: VMWorkerThread, expected: Phase, requested: Phase) -> Result<(), HandshakeError> {
+        if self.phase != expected {
+            return Err(HandshakeError::InvalidPhase(self.phase));
+        }
+        self.phase = requested;
+        self.requester = tls;
+        if requested == Phase::StopRequested {
+            self.requested_at = Some(Instant::now());
+            self.acknowledged.clear();
+        }
+        Ok(())
+    }
+
+    fn is_acknowledged(&self, tls: VMMutatorThread) -> bool {
+        let tls = tls.0.0.to_address().as_usize();
+        self.acknowledged.iter().any(|&(t, _)| t == tls)
+    }
+
+    fn record(&mut self, reached: Phase) {
+        let Some(requested_at) = self.requested_at else {
+            return;
+        };
+        let elapsed = requested_at.elapsed();
+        let nanos = elapsed.as_nanos().min(u64::MAX as u128) as u64;
+        if reached == Phase::Stopped {
+            self.stats.count += 1;
+            self.stats.total_ns = self.stats.total_ns.saturating_add(nanos);
+            self.stats.max_ns = self.stats.max_ns.max(nanos);
+            self.stats.last_ns = nanos;
+            self.histogram[histogram_bucket(elapsed)] += 1;
+            debug!("Mutators stopped in {:?}, {} acknowledged", elapsed, self.acknowledged.len());
+        } else {
+            self.stats.last_pause_ns = nanos;
+        }
+    }
+}
+
+impl Default for Safepoint {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -5,6 +5,7 @@ use std::sync::atomic::AtomicBool;
 use crate::EdgesClosure;
 use crate::NewBuffer;
 use crate::NodesClosure;
+use crate::RangesClosure;
 use crate::ScalaNative;
 use crate::abi::Field_t;
 use crate::abi::Obj;
@@ -14,16 +15,18 @@ use crate::abi::WEAK_REF_FIELD_OFFSET;
 use crate::abi::field_alligned_lock_ref;
 #[cfg(feature = "uses_lockword")]
 use crate::abi::field_is_inflated_lock;
-#[cfg(feature = "object_pinning")]
-use crate::api::mmtk_append_pinned_objects;
-#[cfg(feature = "object_pinning")]
-use crate::api:: mmtk_pin_object;
 use crate::api::release_buffer;
+use crate::roots::mmtk_mark_root_ranges;
+use crate::handles::mmtk_scan_handles;
+#[cfg(not(feature = "mmtk_reference_processor"))]
+use crate::soft_refs::SOFT_REFERENCES;
 use crate::edges::ScalaNativeEdge;
 use atomic::Ordering;
 use log::debug;
 use log::info;
 use mmtk::MutatorContext;
+#[cfg(feature = "object_pinning")]
+use mmtk::memory_manager;
 use mmtk::memory_manager::is_mmtk_object;
 use mmtk::memory_manager::last_heap_address;
 use mmtk::memory_manager::starting_heap_address;
@@ -42,12 +45,156 @@ pub struct VMScanning {}
 
 const WORK_PACKET_CAPACITY: usize = 4096;
 
+use std::cell::Cell;
 use std::sync::Mutex;
 
+use atomic_refcell::AtomicRefCell;
+
 #[repr(C)]
 pub struct ObjectSendPtr(pub *mut Object);
 unsafe impl Send for ObjectSendPtr {}
 
+/// The weak references and ephemerons reached by one GC worker during the current GC.
+/// Only its worker pushes to it while tracing, and it is only drained during weak processing,
+/// once no worker is tracing, so pushing never contends with other workers.
+pub struct WeakRefBuffer {
+    weak_refs: AtomicRefCell<Vec<ObjectSendPtr>>,
+    ephemerons: AtomicRefCell<Vec<ObjectSendPtr>>,
+    /// The thread that pushed to the buffer since it was last drained, checked in debug builds.
+    #[cfg(debug_assertions)]
+    pusher: std::sync::atomic::AtomicUsize,
+}
+// SAFETY: A buffer is touched by two threads only, never at the same time:
+// * its owner, the GC worker it was registered by, pushes to it while tracing, in the
+//   closure phase of a GC. No other thread ever pushes to it;
+// * the worker running `process_weak_refs` drains it in the weak-processing phase, which
+//   only starts once every worker finished tracing and is done before the next closure.
+// `AtomicRefCell` panics rather than allowing overlapping accesses if this is violated, and
+// debug builds check that a single thread pushes to the buffer between two drains.
+unsafe impl Sync for WeakRefBuffer {}
+
+#[cfg(debug_assertions)]
+thread_local! {
+    /// Identifies the current thread by the address of this thread local.
+    static THREAD_MARKER: u8 = 0;
+}
+
+impl WeakRefBuffer {
+    pub const fn new() -> Self {
+        Self {
+            weak_refs: AtomicRefCell::new(Vec::new()),
+            ephemerons: AtomicRefCell::new(Vec::new()),
+            #[cfg(debug_assertions)]
+            pusher: std::sync::atomic::AtomicUsize::new(0),
+        }
+    }
+
+    #[inline]
+    pub fn push(&self, object: *mut Object) {
+        self.check_pusher();
+        self.weak_refs.borrow_mut().push(ObjectSendPtr(object));
+    }
+
+    #[inline]
+    pub fn push_ephemeron(&self, object: *mut Object) {
+        self.check_pusher();
+        self.ephemerons.borrow_mut().push(ObjectSendPtr(object));
+    }
+
+    pub fn drain_into(&self, weak_refs: &mut Vec<ObjectSendPtr>) {
+        weak_refs.append(&mut self.weak_refs.borrow_mut());
+        self.reset_pusher();
+    }
+
+    pub fn drain_ephemerons_into(&self, ephemerons: &mut Vec<ObjectSendPtr>) {
+        ephemerons.append(&mut self.ephemerons.borrow_mut());
+        self.reset_pusher();
+    }
+
+    #[inline]
+    fn check_pusher(&self) {
+        #[cfg(debug_assertions)]
+        {
+            let thread = THREAD_MARKER.with(|marker| marker as *const u8 as usize);
+            let pusher = match self.pusher.compare_exchange(0, thread, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => thread,
+                Err(pusher) => pusher,
+            };
+            debug_assert_eq!(pusher, thread, "A weak reference buffer is pushed to by two threads");
+        }
+    }
+
+    fn reset_pusher(&self) {
+        #[cfg(debug_assertions)]
+        self.pusher.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Default for WeakRefBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A set of weak reference buffers, merged at the weak-processing phase.
+pub struct WeakRefBuffers<'a> {
+    buffers: Mutex<Vec<&'a WeakRefBuffer>>,
+}
+
+impl<'a> WeakRefBuffers<'a> {
+    pub const fn new() -> Self {
+        Self {
+            buffers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn add(&self, buffer: &'a WeakRefBuffer) {
+        self.buffers.lock().unwrap().push(buffer);
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffers.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn drain_into(&self, weak_refs: &mut Vec<ObjectSendPtr>) {
+        for buffer in self.buffers.lock().unwrap().iter() {
+            buffer.drain_into(weak_refs);
+        }
+    }
+
+    pub fn drain_ephemerons_into(&self, ephemerons: &mut Vec<ObjectSendPtr>) {
+        for buffer in self.buffers.lock().unwrap().iter() {
+            buffer.drain_ephemerons_into(ephemerons);
+        }
+    }
+}
+
+impl Default for WeakRefBuffers<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The buffers of all GC workers.
+pub static WEAK_REF_BUFFERS: WeakRefBuffers<'static> = WeakRefBuffers::new();
+
+thread_local! {
+    /// The buffer of the current thread, if it is a GC worker.
+    static LOCAL_WEAK_REF_BUFFER: Cell<Option<&'static WeakRefBuffer>> = Cell::new(None);
+}
+
+/// Make `buffer` the weak reference buffer of the current thread.
+/// Weak references and ephemerons reached by threads without a buffer go to the global
+/// `WEAK_REF_STACK` and `EPHEMERON_STACK`.
+pub fn register_weak_ref_buffer(buffer: &'static WeakRefBuffer) {
+    WEAK_REF_BUFFERS.add(buffer);
+    LOCAL_WEAK_REF_BUFFER.with(|local| local.set(Some(buffer)));
+}
+
 pub struct UsizeSendPtr(*mut *mut usize);
 unsafe impl Send for UsizeSendPtr {}
 
@@ -70,6 +217,24 @@ lazy_static! {
 }
 
 pub static VISITED: AtomicBool = AtomicBool::new(false);
+/// References cleared during the current GC. They are alive until the end of the GC, so they
+/// are not roots, and are only sealed into a batch of `CLEARED_REFERENCES` once it is over.
+static CLEARED_IN_GC: Mutex<Vec<ObjectSendPtr>> = Mutex::new(Vec::new());
+/// The references cleared by the last GCs, one batch per GC. They are handed to
+/// `REFERENCE_QUEUE_HANDLER` by the reference handler thread, so the runtime can put them on
+/// their `ReferenceQueue`s. The slots of a batch never move, so they are reported as root
+/// edges and handed to the handler as they are.
+pub static CLEARED_REFERENCES: Mutex<Vec<Box<[ObjectSendPtr]>>> = Mutex::new(Vec::new());
+/// The batches being handed to `REFERENCE_QUEUE_HANDLER`. The handler may allocate and
+/// trigger a GC, so they are kept as roots until it returns.
+pub static ENQUEUED_REFERENCES: Mutex<Vec<Box<[ObjectSendPtr]>>> = Mutex::new(Vec::new());
+pub static REFERENCE_QUEUE_HANDLER: Mutex<Option<extern "C" fn(references: *const *mut Object, len: usize)>> = Mutex::new(None);
+/// Ephemerons scanned during the current GC whose keys are not known to be reachable yet,
+/// besides those in the buffers of the GC workers.
+pub static EPHEMERON_STACK: Mutex<Vec<ObjectSendPtr>> = Mutex::new(Vec::new());
+/// Whether the soft references started the epoch of the current GC.
+#[cfg(not(feature = "mmtk_reference_processor"))]
+static SOFT_REFS_GC_STARTED: AtomicBool = AtomicBool::new(false);
 pub static HANDLER_FN: Mutex<Option<fn()>> = Mutex::new(None);
 
 extern "C" fn report_edges_and_renew_buffer<F: RootsWorkFactory<ScalaNativeEdge>>(
@@ -181,6 +346,80 @@ impl Drop for RootsClosure {
     }
 }
 
+/// The edge counterpart of `RootsClosure`: buffers root slots and reports them
+/// through an `EdgesClosure`, so their referents may be moved by the GC.
+#[repr(C)]
+pub struct RootEdgesClosure {
+    buffer: *mut Address,
+    cursor: usize,
+    capacity: usize,
+    edges_closure: EdgesClosure,
+}
+
+impl RootEdgesClosure {
+    pub fn new(edges_closure: EdgesClosure) -> Self {
+        let buf = (edges_closure.func)(null_mut(), 0, 0, edges_closure.data as *mut libc::c_void);
+        Self {
+            buffer: buf.ptr as *mut Address,
+            cursor: 0,
+            capacity: buf.capacity,
+            edges_closure,
+        }
+    }
+
+    pub fn do_work(&mut self, slot: Address) {
+        unsafe {
+            *self.buffer.add(self.cursor) = slot;
+        }
+        self.cursor += 1;
+        if self.cursor >= self.capacity {
+            self.flush();
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if self.cursor > 0 {
+            let buf = (self.edges_closure.func)(self.buffer, self.cursor, self.capacity, self.edges_closure.data as *mut libc::c_void);
+            self.buffer = buf.ptr as *mut Address;
+            self.capacity = buf.capacity;
+            self.cursor = 0;
+        }
+    }
+}
+
+impl Drop for RootEdgesClosure {
+    fn drop(&mut self) {
+        if self.cursor > 0 {
+            self.flush();
+        }
+        if !self.buffer.is_null() {
+            let _vec = unsafe { Vec::<Address>::from_raw_parts(self.buffer, self.cursor, self.capacity) };
+        }
+    }
+}
+
+/// Remember a weak reference reached during tracing, so its referent can be processed once
+/// the transitive closure is complete. With `mmtk_reference_processor`, the runtime registers
+/// references through `mmtk_add_weak_candidate` and friends instead.
+#[inline]
+pub(crate) fn push_weak_reference(object: *mut Object) {
+    #[cfg(not(feature = "mmtk_reference_processor"))]
+    LOCAL_WEAK_REF_BUFFER.with(|local| match local.get() {
+        Some(buffer) => buffer.push(object),
+        None => WEAK_REF_STACK.lock().unwrap().push(ObjectSendPtr(object)),
+    });
+    #[cfg(feature = "mmtk_reference_processor")]
+    let _ = object;
+}
+
+#[inline]
+pub(crate) fn push_ephemeron(object: *mut Object) {
+    LOCAL_WEAK_REF_BUFFER.with(|local| match local.get() {
+        Some(buffer) => buffer.push_ephemeron(object),
+        None => EPHEMERON_STACK.lock().unwrap().push(ObjectSendPtr(object)),
+    });
+}
+
 pub(crate) fn is_word_in_heap(address: *mut usize) -> bool {
     let address_num = address as usize;
     address_num >= starting_heap_address().as_usize() && 
@@ -209,7 +448,7 @@ pub fn mmtk_mark_object(
         debug_assert!(!(*object).rtti.is_null());
         mmtk_mark_lock_words(object, roots_closure);
         if (*object).is_weak_reference() {
-            WEAK_REF_STACK.lock().unwrap().push(ObjectSendPtr(object));
+            push_weak_reference(object);
         }
 
         debug_assert!((*object).size() != 0);
@@ -234,16 +473,16 @@ pub fn mmtk_mark_field(
 pub fn mmtk_mark_conservative(
     address: *mut usize,
     roots_closure: &mut RootsClosure,
-) {
+) -> Option<ObjectReference> {
     debug_assert!(is_word_in_heap(address));
     let mask = *(ALLOCATION_ALIGNMENT_INVERSE_MASK);
     let object = ((address as usize) & mask) as *mut usize as *mut Object;
     let object_addr = Address::from_mut_ptr(object);
-    if !object.is_null() {
-        if is_mmtk_object(object_addr) {
-            mmtk_mark_object(object, roots_closure);
-        }
+    if !object.is_null() && is_mmtk_object(object_addr) {
+        mmtk_mark_object(object, roots_closure);
+        return Some(ObjectReference::from_raw_address(object_addr));
     }
+    None
 }
 
 #[inline]
@@ -267,29 +506,29 @@ pub fn mmtk_mark_lock_words(
     }
 }
 
-pub unsafe fn mmtk_mark_modules(
-    roots_closure: &mut RootsClosure,  
+/// Report the module slots holding heap objects as root edges. Unlike the words found on
+/// stacks, module slots are precise, so their objects are not pinned and may be moved.
+/// Tracing a root edge does not visit the lock words of its object, so their monitors are
+/// reported to `roots_closure`.
+pub unsafe fn mmtk_scan_modules(
+    edges_closure: &mut RootEdgesClosure,
+    roots_closure: &mut RootsClosure,
 ) {
     let modules = (*(__MODULES.lock().unwrap())).0;
     let nb_modules = *(__MODULES_SIZE);
 
-    #[cfg(feature = "object_pinning")]
-    let mut current_pinned_objects = Vec::new();
     for i in 0..nb_modules {
         let edge = modules.offset(i as isize);
         let node = *edge;
-        let object = node as *mut Object;
-        #[cfg(feature = "object_pinning")]
-        {
-            let obj_ref = ObjectReference::from_raw_address(Address::from_mut_ptr(node));
-            if memory_manager::pin_object::<ScalaNative>(obj_ref) {
-                current_pinned_objects.push(obj_ref);
+        if is_word_in_heap(node) && is_mmtk_object(Address::from_mut_ptr(node)) {
+            let object = node as *mut Object;
+            if (*object).is_weak_reference() {
+                push_weak_reference(object);
             }
+            mmtk_mark_lock_words(object, roots_closure);
+            edges_closure.do_work(Address::from_mut_ptr(edge));
         }
-        mmtk_mark_field(object as Field_t, roots_closure)
     }
-    #[cfg(feature = "object_pinning")]
-    crate::binding().pinned_objects.lock().unwrap().append(&mut current_pinned_objects);
 }
 
 pub unsafe fn mmtk_mark_range(
@@ -307,14 +546,15 @@ pub unsafe fn mmtk_mark_range(
     while current <= to {
         let addr = *current;
         if is_word_in_heap(addr) && is_ptr_aligned(addr) {
+            let _object = mmtk_mark_conservative(addr, roots_closure);
+            // Only pin what the word resolved to: the word itself may point into the middle
+            // of an object, or at nothing MMTk allocated.
             #[cfg(feature = "object_pinning")]
-            {
-                let obj_ref = ObjectReference::from_raw_address(Address::from_mut_ptr(addr));
-                if memory_manager::pin_object::<ScalaNative>(obj_ref) {
-                    current_pinned_objects.push(obj_ref);
+            if let Some(object) = _object {
+                if memory_manager::pin_object::<ScalaNative>(object) {
+                    current_pinned_objects.push(object);
                 }
-            } 
-            mmtk_mark_conservative(addr, roots_closure);
+            }
         }
         current = current.offset(1);
     }
@@ -334,58 +574,226 @@ pub unsafe fn mmtk_mark_program_stack(tls: VMMutatorThread, roots_closure: &mut
         stack_range.stack_bottom, roots_closure);
     mmtk_mark_range(regs_range.regs, 
         regs_range.regs.add(regs_range.regs_size), roots_closure);
+
+    for (start, end) in get_extra_ranges(tls) {
+        // The extra ranges are half-open, while `mmtk_mark_range` includes its upper bound.
+        mmtk_mark_range(start, end.offset(-1), roots_closure);
+    }
 }
 
-fn scan_roots_in_all_mutator_threads<F: RootsWorkFactory<ScalaNativeEdge>>(_tls: VMWorkerThread, _factory: &mut F) {
+/// Collect the extra conservative ranges (TLS block, signal and coroutine stacks) of a mutator.
+/// Empty ranges are dropped.
+pub(crate) fn get_extra_ranges(tls: VMMutatorThread) -> Vec<(*mut *mut usize, *mut *mut usize)> {
+    let mut ranges = Vec::new();
     unsafe {
-        let nodes_closure = to_nodes_closure(_factory);
-        let mut roots_closure = RootsClosure::new(nodes_closure);
-        let mut head = ((*UPCALLS).get_mutator_threads)();
-        while !head.is_null() {
-            let node = &*head;
-            let thread = node.value;
-            let tls = VMMutatorThread(VMThread(OpaquePointer::from_address(Address::from_mut_ptr(thread))));
-            mmtk_mark_program_stack(tls, &mut roots_closure);
-            head = node.next;
-        }
+        ((*UPCALLS).get_extra_ranges)(tls, RangesClosure::from_rust_closure(&mut |start, end| {
+            if !start.is_null() && start < end {
+                ranges.push((start, end));
+            }
+        }));
     }
+    ranges
 }
 
-fn weak_ref_stack_is_empty() -> bool {
-    let weak_refs = WEAK_REF_STACK.lock().unwrap();
-    weak_refs.is_empty()
+/// Take the weak references pushed during the current GC, merging the workers' buffers.
+pub(crate) fn weak_ref_stack_take() -> Vec<ObjectSendPtr> {
+    let mut weak_refs = std::mem::take(&mut *WEAK_REF_STACK.lock().unwrap());
+    WEAK_REF_BUFFERS.drain_into(&mut weak_refs);
+    weak_refs
 }
 
-fn weak_ref_stack_pop() -> Option<ObjectSendPtr> {
-    let mut weak_refs = WEAK_REF_STACK.lock().unwrap();
-    weak_refs.pop()
+/// What weak processing needs from the GC, so that it can be run against a mock heap.
+pub(crate) trait WeakProcessingContext {
+    /// The current address of `object` if it is alive, or `None` if it is dead.
+    fn forward(&mut self, object: ObjectReference) -> Option<ObjectReference>;
+    /// Keep `object` alive and return its current address.
+    fn retain(&mut self, object: ObjectReference) -> ObjectReference;
+}
+
+pub(crate) struct TracerWeakContext<'a, T: mmtk::vm::ObjectTracer>(pub &'a mut T);
+
+impl<'a, T: mmtk::vm::ObjectTracer> WeakProcessingContext for TracerWeakContext<'a, T> {
+    fn forward(&mut self, object: ObjectReference) -> Option<ObjectReference> {
+        if !is_word_in_heap(object.to_raw_address().to_mut_ptr()) {
+            Some(object)
+        } else if object.is_reachable() {
+            // The object is already marked, so this only looks up its new address.
+            Some(self.0.trace_object(object))
+        } else {
+            None
+        }
+    }
+
+    fn retain(&mut self, object: ObjectReference) -> ObjectReference {
+        if !is_word_in_heap(object.to_raw_address().to_mut_ptr()) {
+            object
+        } else {
+            self.0.trace_object(object)
+        }
+    }
 }
 
+/// Clear the referents of the weak references on `WEAK_REF_STACK` that did not survive
+/// the transitive closure, and update the surviving referents to their new location.
 pub fn mmtk_weak_ref_stack_nullify(closure: &mut impl mmtk::vm::ObjectTracer) {
-    VISITED.store(false, Ordering::SeqCst);
-    while !weak_ref_stack_is_empty() {
-        let weak_ref = weak_ref_stack_pop().unwrap();
-        let object = weak_ref.0;
-        let object_ref = ObjectReference::from_raw_address(Address::from_mut_ptr(object));
-        let traced = closure.trace_object(object_ref);
-        let traced_obj = Obj::from(traced);
-        let fields = traced_obj.get_fields();
-        let field_offset = *WEAK_REF_FIELD_OFFSET;
-        let edge = unsafe { fields.offset(field_offset as isize) };
-        let ref_object = unsafe { *edge };
-        let ref_object_ref = ObjectReference::from_raw_address(Address::from_mut_ptr(ref_object));
-        let traced_ref_object = closure.trace_object(ref_object_ref);
-        if is_word_in_heap(ref_object as *mut usize) {
-            // println!("weak_ref_stack_nullify: {:x}", ref_object as usize);
-            if !traced_ref_object.is_reachable() {
-                let edge_addr = Address::from_mut_ptr(edge);
+    let weak_refs = weak_ref_stack_take();
+    let mut context = TracerWeakContext(closure);
+    let cleared = nullify_weak_refs(weak_refs, |object| context.forward(object));
+    VISITED.store(!cleared.is_empty(), Ordering::SeqCst);
+    record_cleared_references(&cleared);
+}
+
+/// Take the pending ephemerons, merging those pushed to the workers' buffers since the last call.
+fn ephemerons_take() -> Vec<ObjectSendPtr> {
+    let mut ephemerons = std::mem::take(&mut *EPHEMERON_STACK.lock().unwrap());
+    WEAK_REF_BUFFERS.drain_ephemerons_into(&mut ephemerons);
+    ephemerons
+}
+
+fn as_object_reference(field: Field_t) -> ObjectReference {
+    ObjectReference::from_raw_address(Address::from_mut_ptr(field))
+}
+
+/// Trace the values of the pending ephemerons whose keys are reachable, and update their
+/// keys and values. Returns true if any value was traced, in which case the transitive
+/// closure must be expanded and the ephemerons processed again, until a fixpoint is reached.
+pub(crate) fn resolve_ephemerons(context: &mut impl WeakProcessingContext) -> bool {
+    let ephemerons = ephemerons_take();
+    let mut unresolved = Vec::new();
+    let mut traced = false;
+    for ephemeron in ephemerons {
+        // The ephemeron may have been moved after it was pushed.
+        let Some(current) = context.forward(as_object_reference(ephemeron.0 as Field_t)) else {
+            continue;
+        };
+        let ephemeron = ObjectSendPtr(current.to_raw_address().to_mut_ptr());
+        let object = unsafe { &*ephemeron.0 };
+        let key_slot = object.ephemeron_key_slot();
+        let key = unsafe { *key_slot };
+        let new_key = if key.is_null() { None } else { context.forward(as_object_reference(key)) };
+        let Some(new_key) = new_key else {
+            unresolved.push(ephemeron);
+            continue;
+        };
+        unsafe { Address::from_mut_ptr(key_slot).store(new_key) };
+        let value_slot = object.ephemeron_value_slot();
+        let value = unsafe { *value_slot };
+        if !value.is_null() {
+            let new_value = context.retain(as_object_reference(value));
+            unsafe { Address::from_mut_ptr(value_slot).store(new_value) };
+            traced = true;
+        }
+    }
+    EPHEMERON_STACK.lock().unwrap().extend(unresolved);
+    traced
+}
+
+/// Clear the key and value of the remaining ephemerons, whose keys are dead.
+/// Must only be called once `resolve_ephemerons` reached a fixpoint.
+pub(crate) fn clear_dead_ephemerons() {
+    let ephemerons = ephemerons_take();
+    for ephemeron in ephemerons {
+        let object = unsafe { &*ephemeron.0 };
+        let null_ptr: *mut usize = null_mut();
+        unsafe {
+            Address::from_mut_ptr(object.ephemeron_key_slot()).store(null_ptr);
+            Address::from_mut_ptr(object.ephemeron_value_slot()).store(null_ptr);
+        }
+    }
+}
+
+pub(crate) fn record_cleared_references(references: &[ObjectReference]) {
+    if !references.is_empty() {
+        let mut cleared = CLEARED_IN_GC.lock().unwrap();
+        cleared.extend(references.iter().map(|r| ObjectSendPtr(r.to_raw_address().to_mut_ptr())));
+    }
+}
+
+/// Finish a GC: make the references it cleared one batch of `CLEARED_REFERENCES`.
+pub(crate) fn seal_cleared_references() {
+    let cleared = std::mem::take(&mut *CLEARED_IN_GC.lock().unwrap());
+    if !cleared.is_empty() {
+        CLEARED_REFERENCES.lock().unwrap().push(cleared.into_boxed_slice());
+    }
+}
+
+/// Hand the references cleared by the last GCs to the registered handler, one batch per GC.
+/// A batch is only valid for the duration of the call.
+pub fn mmtk_enqueue_cleared_references() {
+    let handler = *REFERENCE_QUEUE_HANDLER.lock().unwrap();
+    loop {
+        let (references, len) = {
+            // Both locks are held so a root scan never sees the batch in neither or both.
+            let mut cleared = CLEARED_REFERENCES.lock().unwrap();
+            if cleared.is_empty() {
+                return;
+            }
+            let mut enqueued = ENQUEUED_REFERENCES.lock().unwrap();
+            enqueued.push(cleared.remove(0));
+            let batch = enqueued.last().unwrap();
+            (batch.as_ptr(), batch.len())
+        };
+        if let Some(handler) = handler {
+            debug!("Enqueueing {} cleared references", len);
+            // The batch stays where it is, and its slots are updated in place if the handler triggers a GC.
+            handler(references as *const *mut Object, len);
+        }
+        ENQUEUED_REFERENCES.lock().unwrap().retain(|batch| batch.as_ptr() != references);
+    }
+}
+
+/// Report the cleared references that were not handed to the runtime yet as root edges.
+/// The slots are those of the batches, which stay in place until they are dropped.
+pub fn mmtk_scan_cleared_references(edges_closure: &mut RootEdgesClosure, roots_closure: &mut RootsClosure) {
+    let cleared = CLEARED_REFERENCES.lock().unwrap();
+    let enqueued = ENQUEUED_REFERENCES.lock().unwrap();
+    for batch in cleared.iter().chain(enqueued.iter()) {
+        for reference in batch.iter() {
+            mmtk_mark_lock_words(reference.0, roots_closure);
+            edges_closure.do_work(Address::from_ref(reference));
+        }
+    }
+}
+
+/// Process the weak references in `weak_refs`. `forward` returns the current address of
+/// a live object, or `None` if the object is dead. A weak reference may have been pushed
+/// several times, and before it was moved, so it is forwarded and deduplicated first.
+/// Returns the weak references whose referents were cleared.
+pub(crate) fn nullify_weak_refs(
+    weak_refs: Vec<ObjectSendPtr>,
+    mut forward: impl FnMut(ObjectReference) -> Option<ObjectReference>,
+) -> Vec<ObjectReference> {
+    let field_offset = *WEAK_REF_FIELD_OFFSET as isize;
+    let mut visited = HashSet::new();
+    let mut cleared = Vec::new();
+    for weak_ref in weak_refs {
+        let weak_ref = ObjectReference::from_raw_address(Address::from_mut_ptr(weak_ref.0));
+        // Weak references are only pushed when they are reached, so they must be alive.
+        let forwarded = forward(weak_ref);
+        debug_assert!(forwarded.is_some(), "Weak reference {:?} is not alive", weak_ref);
+        let Some(weak_ref) = forwarded else {
+            continue;
+        };
+        if !visited.insert(weak_ref) {
+            continue;
+        }
+        let edge = unsafe { Obj::from(weak_ref).get_fields().offset(field_offset) };
+        let referent = unsafe { *edge };
+        if referent.is_null() {
+            continue;
+        }
+        let referent = ObjectReference::from_raw_address(Address::from_mut_ptr(referent));
+        let edge_addr = Address::from_mut_ptr(edge);
+        match forward(referent) {
+            Some(new_referent) => unsafe { edge_addr.store(new_referent) },
+            None => {
                 let null_ptr: *mut usize = null_mut();
-                // info!("Nullifying weak ref: {:x}", edge_addr.as_usize());
                 unsafe { edge_addr.store(null_ptr) };
-                VISITED.store(true, Ordering::SeqCst);
+                cleared.push(weak_ref);
             }
         }
     }
+    cleared
 }
 
 pub fn mmtk_weak_ref_stack_call_handlers() {
@@ -414,10 +822,16 @@ impl Scanning<ScalaNative> for VMScanning {
 
     fn scan_vm_specific_roots(_tls: VMWorkerThread, mut _factory: impl RootsWorkFactory<ScalaNativeEdge>) {
         unsafe {
-            scan_roots_in_all_mutator_threads(_tls, &mut _factory);
+            // The stacks are scanned in `scan_roots_in_mutator_thread`, for each mutator visited in `stop_all_mutators`.
             let nodes_closure = to_nodes_closure(&mut _factory);
             let mut roots_closure = RootsClosure::new(nodes_closure);
-            mmtk_mark_modules(&mut roots_closure);
+            // Root ranges registered by native code are ambiguous, like stacks.
+            mmtk_mark_root_ranges(&mut roots_closure);
+            let edges_closure = to_edges_closure(&mut _factory);
+            let mut root_edges_closure = RootEdgesClosure::new(edges_closure);
+            mmtk_scan_modules(&mut root_edges_closure, &mut roots_closure);
+            mmtk_scan_handles(&mut root_edges_closure, &mut roots_closure);
+            mmtk_scan_cleared_references(&mut root_edges_closure, &mut roots_closure);
         }
     }
 
@@ -459,13 +873,43 @@ impl Scanning<ScalaNative> for VMScanning {
             _worker: &mut mmtk::scheduler::GCWorker<ScalaNative>,
             _tracer_context: impl mmtk::vm::ObjectTracerContext<ScalaNative>,
         ) -> bool {
+        debug!("process_weak_refs");
+        #[cfg(not(feature = "mmtk_reference_processor"))]
+        {
+            // Soft referents kept by the policy must be traced before any weak reference is
+            // processed. Tracing them expands the transitive closure, after which we are called
+            // again, until no soft reference that became reachable retains anything new.
+            if !SOFT_REFS_GC_STARTED.swap(true, Ordering::SeqCst) {
+                SOFT_REFERENCES.start_gc();
+            }
+            let emergency = crate::SINGLETON.is_emergency_collection();
+            let retained = _tracer_context.with_tracer(_worker, |object_tracer| {
+                SOFT_REFERENCES.retain_referents(emergency, object_tracer)
+            });
+            if retained {
+                debug!("process_weak_refs: soft referents retained, expanding the closure");
+                return true;
+            }
+        }
+        // Iterate until no ephemeron with a reachable key is left.
+        let traced = _tracer_context.with_tracer(_worker, |object_tracer| {
+            resolve_ephemerons(&mut TracerWeakContext(object_tracer))
+        });
+        if traced {
+            debug!("process_weak_refs: ephemeron values traced, expanding the closure");
+            return true;
+        }
+        clear_dead_ephemerons();
+        #[cfg(not(feature = "mmtk_reference_processor"))]
+        {
+            _tracer_context.with_tracer(_worker, |object_tracer| {
+                mmtk_weak_ref_stack_nullify(object_tracer);
+                SOFT_REFERENCES.forward(object_tracer);
+            });
+            SOFT_REFS_GC_STARTED.store(false, Ordering::SeqCst);
+        }
         #[cfg(feature = "object_pinning")]  
         crate::binding().unpin_pinned_objects();
-        debug!("process_weak_refs");
-        // _tracer_context.with_tracer(_worker, |object_tracer| {
-        //     mmtk_weak_ref_stack_nullify(object_tracer);
-        // });
-        // mmtk_weak_ref_stack_call_handlers();
         debug!("process_weak_refs done");
         false
     }
@@ -0,0 +1,150 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use log::warn;
+use mmtk::util::ObjectReference;
+use mmtk::vm::ObjectTracer;
+
+use crate::abi::Obj;
+use crate::scanning::is_word_in_heap;
+
+/// Soft references are retained while at least this percentage of the heap was free after the last GC.
+pub const DEFAULT_FREE_HEAP_PERCENT: usize = 20;
+/// Under heap pressure, soft references not accessed during the last few GCs are cleared.
+pub const DEFAULT_MAX_IDLE_GCS: u64 = 4;
+
+lazy_static! {
+    pub static ref SOFT_REFERENCES: SoftReferences = SoftReferences::new();
+}
+
+/// The soft references registered through `mmtk_add_soft_candidate`.
+///
+/// Soft references share the weak reference layout and are put on the weak ref stack like
+/// weak references. Before weak references are processed, the referents of the soft
+/// references that the policy retains are traced, so they survive the GC:
+/// * While enough of the heap was free after the last GC, all soft referents are retained.
+/// * Under heap pressure, only soft references accessed during the last `max_idle_gcs`
+///   GCs are retained, so the least recently accessed ones are cleared first.
+/// * In an emergency GC, i.e. when an allocation is about to fail, only the soft references
+///   accessed since the last GC are retained.
+///
+/// The GC epoch during which a soft reference was last accessed is kept in the reference
+/// itself, in the timestamp field registered through `mmtk_register_soft_ref_timestamp`,
+/// so recording an access takes no lock. Until that field is registered, soft references
+/// are not registered either, and behave like weak references.
+pub struct SoftReferences {
+    /// Registered soft references.
+    refs: Mutex<Vec<ObjectReference>>,
+    epoch: AtomicU64,
+    free_heap_percent: AtomicUsize,
+    max_idle_gcs: AtomicU64,
+    /// Free heap, in percent, measured when the mutators resumed after the last GC.
+    last_free_heap_percent: AtomicUsize,
+}
+
+impl SoftReferences {
+    pub fn new() -> Self {
+        Self {
+            refs: Mutex::new(Vec::new()),
+            epoch: AtomicU64::new(0),
+            free_heap_percent: AtomicUsize::new(DEFAULT_FREE_HEAP_PERCENT),
+            max_idle_gcs: AtomicU64::new(DEFAULT_MAX_IDLE_GCS),
+            last_free_heap_percent: AtomicUsize::new(100),
+        }
+    }
+
+    pub fn set_policy(&self, free_heap_percent: usize, max_idle_gcs: u64) {
+        self.free_heap_percent.store(free_heap_percent, Ordering::SeqCst);
+        self.max_idle_gcs.store(max_idle_gcs, Ordering::SeqCst);
+    }
+
+    pub fn register(&self, reference: ObjectReference) {
+        if Obj::from(reference).soft_ref_timestamp().is_none() {
+            warn!("Soft reference {:?} is treated as a weak reference: no timestamp field is registered", reference);
+            return;
+        }
+        self.touch(reference);
+        self.refs.lock().unwrap().push(reference);
+    }
+
+    /// Record an access to `reference`, e.g. from `SoftReference.get`.
+    pub fn touch(&self, reference: ObjectReference) {
+        if let Some(timestamp) = Obj::from(reference).soft_ref_timestamp() {
+            timestamp.store(self.epoch.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+    }
+
+    fn last_access(reference: ObjectReference) -> u64 {
+        Obj::from(reference).soft_ref_timestamp().map_or(0, |timestamp| timestamp.load(Ordering::Relaxed))
+    }
+
+    pub fn len(&self) -> usize {
+        self.refs.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn record_free_heap(&self, free_bytes: usize, total_bytes: usize) {
+        let percent = if total_bytes == 0 { 100 } else { free_bytes * 100 / total_bytes };
+        self.last_free_heap_percent.store(percent, Ordering::Relaxed);
+    }
+
+    /// The oldest access epoch a soft reference may have to be retained in the GC of `epoch`.
+    pub(crate) fn retention_epoch(&self, epoch: u64, emergency: bool) -> u64 {
+        if emergency {
+            // The accesses since the last GC were made during the previous epoch.
+            epoch.saturating_sub(1)
+        } else if self.last_free_heap_percent.load(Ordering::Relaxed) >= self.free_heap_percent.load(Ordering::Relaxed) {
+            0
+        } else {
+            epoch.saturating_sub(self.max_idle_gcs.load(Ordering::Relaxed))
+        }
+    }
+
+    /// Start a GC, i.e. a new epoch.
+    pub fn start_gc(&self) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Trace the referents of the reachable soft references the policy retains.
+    /// Returns true if any referent was not reachable yet. The transitive closure then needs
+    /// to be expanded, and this called again, as more soft references may have become
+    /// reachable through the referents, until a fixpoint is reached.
+    pub fn retain_referents(&self, emergency: bool, tracer: &mut impl ObjectTracer) -> bool {
+        let min_epoch = self.retention_epoch(self.epoch.load(Ordering::SeqCst), emergency);
+        let refs = self.refs.lock().unwrap();
+        let mut traced = false;
+        for &reference in refs.iter() {
+            if !reference.is_reachable() || Self::last_access(reference) < min_epoch {
+                continue;
+            }
+            let reference = tracer.trace_object(reference);
+            let referent = crate::reference_glue::referent_of(reference);
+            if referent.is_null() || !is_word_in_heap(referent.to_raw_address().to_mut_ptr()) {
+                continue;
+            }
+            traced |= !referent.is_reachable();
+            let new_referent = tracer.trace_object(referent);
+            crate::reference_glue::set_referent_of(reference, new_referent);
+        }
+        traced
+    }
+
+    /// Finish a GC: drop the dead soft references and update the moved ones.
+    pub fn forward(&self, tracer: &mut impl ObjectTracer) {
+        let mut refs = self.refs.lock().unwrap();
+        *refs = refs
+            .drain(..)
+            .filter(|reference| reference.is_reachable())
+            .map(|reference| tracer.trace_object(reference))
+            .collect();
+    }
+}
+
+impl Default for SoftReferences {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use mmtk::util::{Address, ObjectReference};
+
+use crate::abi::Object;
+use crate::api::mmtk_register_ephemerons;
+use crate::scanning::{clear_dead_ephemerons, push_ephemeron, resolve_ephemerons, WeakProcessingContext};
+use crate::tests::fixtures::mock_vm::*;
+
+const EPHEMERON_ID: i32 = 40;
+const KEY_OFFSET: usize = 0;
+const VALUE_OFFSET: usize = 1;
+
+fn objref(addr: usize) -> ObjectReference {
+    ObjectReference::from_raw_address(unsafe { Address::from_usize(addr) })
+}
+
+fn object_of(memory: &mut [usize]) -> *mut Object {
+    memory.as_mut_ptr() as *mut Object
+}
+
+/// A mock heap: `live` maps the marked objects to their new address, and `edges` lists
+/// the objects that become reachable when an object is retained.
+struct MockHeap {
+    live: HashMap<ObjectReference, ObjectReference>,
+    edges: HashMap<ObjectReference, ObjectReference>,
+}
+
+impl WeakProcessingContext for MockHeap {
+    fn forward(&mut self, object: ObjectReference) -> Option<ObjectReference> {
+        self.live.get(&object).copied()
+    }
+
+    fn retain(&mut self, object: ObjectReference) -> ObjectReference {
+        let mut current = object;
+        // Mark the transitive closure of `object`, which is what the GC does after we return.
+        while !self.live.contains_key(&current) {
+            self.live.insert(current, current);
+            match self.edges.get(&current) {
+                Some(&next) => current = next,
+                None => break,
+            }
+        }
+        self.live[&object]
+    }
+}
+
+#[test]
+pub fn resolve_mock_graph() {
+    install_mock_upcalls();
+    mmtk_register_ephemerons(EPHEMERON_ID, EPHEMERON_ID, KEY_OFFSET as i32, VALUE_OFFSET as i32);
+    let mut rtti = MockRtti::new(EPHEMERON_ID, std::mem::size_of::<Object>() + 16, std::ptr::null_mut());
+
+    // The keys and values are never dereferenced, so they do not need to be real objects.
+    const LIVE_KEY: usize = 0x10000;
+    const LIVE_VALUE: usize = 0x18000;
+    const CHAINED_VALUE: usize = 0x20000;
+    const DEAD_KEY: usize = 0x30000;
+    const DEAD_VALUE: usize = 0x38000;
+    const MOVED_KEY: usize = 0x40000;
+    const MOVED_KEY_TO: usize = 0x48000;
+
+    // Its key is the value of `live`, which is processed after it, so it is only resolved
+    // in the second round.
+    let mut chained = mock_object(rtti.as_ptr(), &[LIVE_VALUE, CHAINED_VALUE]);
+    let mut live = mock_object(rtti.as_ptr(), &[LIVE_KEY, LIVE_VALUE]);
+    let mut dead = mock_object(rtti.as_ptr(), &[DEAD_KEY, DEAD_VALUE]);
+    let mut moved = mock_object(rtti.as_ptr(), &[MOVED_KEY, 0]);
+    let mut null_key = mock_object(rtti.as_ptr(), &[0, DEAD_VALUE]);
+
+    let mut heap = MockHeap { live: HashMap::new(), edges: HashMap::new() };
+    heap.live.insert(objref(LIVE_KEY), objref(LIVE_KEY));
+    heap.live.insert(objref(MOVED_KEY), objref(MOVED_KEY_TO));
+
+    for memory in [&mut chained, &mut live, &mut dead, &mut moved, &mut null_key] {
+        let object = objref(object_of(memory) as usize);
+        heap.live.insert(object, object);
+        push_ephemeron(object_of(memory));
+    }
+
+    let mut rounds = 0;
+    while resolve_ephemerons(&mut heap) {
+        rounds += 1;
+        assert!(rounds <= 5, "ephemeron processing does not reach a fixpoint");
+    }
+    assert_eq!(rounds, 2);
+    clear_dead_ephemerons();
+
+    let field = |memory: &[usize], offset: usize| memory[HEADER_WORDS + offset];
+    assert_eq!(field(&live, KEY_OFFSET), LIVE_KEY);
+    assert_eq!(field(&live, VALUE_OFFSET), LIVE_VALUE);
+    assert_eq!(field(&chained, KEY_OFFSET), LIVE_VALUE);
+    assert_eq!(field(&chained, VALUE_OFFSET), CHAINED_VALUE);
+    assert_eq!(field(&dead, KEY_OFFSET), 0);
+    assert_eq!(field(&dead, VALUE_OFFSET), 0);
+    assert_eq!(field(&moved, KEY_OFFSET), MOVED_KEY_TO);
+    assert_eq!(field(&moved, VALUE_OFFSET), 0);
+    assert_eq!(field(&null_key, KEY_OFFSET), 0);
+    assert_eq!(field(&null_key, VALUE_OFFSET), 0);
+    assert!(!heap.live.contains_key(&objref(DEAD_VALUE)));
+}
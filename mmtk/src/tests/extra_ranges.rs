@@ -0,0 +1,33 @@
+use crate::scanning::get_extra_ranges;
+use crate::tests::fixtures::mock_vm::{install_mock_upcalls, MockMutatorThread};
+
+fn range_of(words: &[usize]) -> (*mut *mut usize, *mut *mut usize) {
+    let start = words.as_ptr() as *mut *mut usize;
+    (start, unsafe { start.add(words.len()) })
+}
+
+#[test]
+pub fn tls_and_extra_stacks() {
+    install_mock_upcalls();
+    let mut thread = MockMutatorThread::new();
+    thread.tls_block = vec![0; 4];
+    thread.extra_stacks = vec![vec![0; 8], vec![0; 16]];
+
+    let ranges = get_extra_ranges(thread.tls());
+    assert_eq!(ranges, vec![
+        range_of(&thread.tls_block),
+        range_of(&thread.extra_stacks[0]),
+        range_of(&thread.extra_stacks[1]),
+    ]);
+}
+
+#[test]
+pub fn empty_ranges_are_dropped() {
+    install_mock_upcalls();
+    let mut thread = MockMutatorThread::new();
+    thread.extra_stacks = vec![vec![], vec![0; 2]];
+
+    // Neither the empty TLS block nor the empty stack segment is reported.
+    let ranges = get_extra_ranges(thread.tls());
+    assert_eq!(ranges, vec![range_of(&thread.extra_stacks[1])]);
+}
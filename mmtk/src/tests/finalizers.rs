@@ -0,0 +1,48 @@
+use std::sync::atomic::Ordering;
+
+use mmtk::util::Address;
+
+use crate::api::*;
+use crate::reference_handler::REFERENCE_HANDLER;
+use crate::tests::fixtures::mock_vm::*;
+use crate::tests::fixtures::MOCK_GC;
+
+#[test]
+pub fn unreachable_finalizable_objects_are_returned_once() {
+    MOCK_GC.with_fixture(|fixture| {
+        let mut rtti = MockRtti::plain();
+        let reachable = fixture.alloc(rtti.as_ptr(), &[1]);
+        let unreachable = fixture.alloc(rtti.as_ptr(), &[2]);
+        mmtk_add_finalizer(reachable);
+        mmtk_add_finalizer(unreachable);
+        set_mock_module(0, reachable.to_raw_address());
+        let finalizers_scheduled = MOCK_FINALIZERS_SCHEDULED.load(Ordering::SeqCst);
+
+        fixture.collect();
+        REFERENCE_HANDLER.wait_until_idle();
+
+        // The runtime is asked to run the finalizers once the mutators are resumed.
+        assert_eq!(MOCK_FINALIZERS_SCHEDULED.load(Ordering::SeqCst), finalizers_scheduled + 1);
+        // The unreachable object was kept alive, possibly moved, to be finalized.
+        let finalized = mmtk_get_finalized_object();
+        assert!(!finalized.is_null());
+        #[cfg(feature = "is_mmtk_object")]
+        assert!(mmtk_is_mmtk_object(finalized.to_raw_address()));
+        assert_eq!(payload_of(finalized.to_raw_address()), 2);
+        assert!(mmtk_get_finalized_object().is_null());
+
+        // Neither the finalized object nor the reachable one is returned by a later GC.
+        fixture.collect();
+        REFERENCE_HANDLER.wait_until_idle();
+        assert!(mmtk_get_finalized_object().is_null());
+
+        // Once the reachable object is unreachable too, the next GC returns it.
+        set_mock_module(0, Address::ZERO);
+        fixture.collect();
+        REFERENCE_HANDLER.wait_until_idle();
+        let finalized = mmtk_get_finalized_object();
+        assert!(!finalized.is_null());
+        assert_eq!(payload_of(finalized.to_raw_address()), 1);
+        assert!(mmtk_get_finalized_object().is_null());
+    });
+}
@@ -0,0 +1,286 @@
+// A mock Scala Native runtime. It provides just enough of the upcalls for the binding to
+// run without the C runtime: mutator threads are `MockMutatorThread`s whose stacks and
+// extra ranges are plain Rust vectors.
+
+use std::sync::{Condvar, Mutex, Once};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use mmtk::Mutator;
+use once_cell::sync::OnceCell;
+use mmtk::util::Address;
+use mmtk::util::alloc::AllocationError;
+use mmtk::util::opaque_pointer::*;
+
+use crate::abi::{word_t, GCThreadTLS, Object, Rtti, Runtime};
+use crate::collection::SendCtxPtr;
+use crate::mutators::MUTATORS;
+use crate::object_scanning::LAST_FIELD_OFFSET;
+use crate::safepoint::SAFEPOINT;
+use crate::{NodesClosure, RangesClosure, RegsRange, ScalaNative, ScalaNativeUpcalls, StackRange, UPCALLS};
+
+pub const MOCK_OBJECT_ARRAY_ID: i32 = 1;
+pub const MOCK_ARRAY_IDS_MIN: i32 = 0;
+pub const MOCK_ARRAY_IDS_MAX: i32 = 9;
+pub const MOCK_WEAK_REF_IDS_MIN: i32 = 20;
+pub const MOCK_WEAK_REF_IDS_MAX: i32 = 21;
+pub const MOCK_WEAK_REF_FIELD_OFFSET: i32 = 0;
+pub const MOCK_ALLOCATION_ALIGNMENT: usize = 16;
+/// The rtti ids of `MockRtti::plain` and `MockRtti::holder`.
+pub const MOCK_PLAIN_OBJECT_ID: i32 = 50;
+pub const MOCK_HOLDER_OBJECT_ID: i32 = 51;
+
+static NO_REFERENCES: [i64; 1] = [LAST_FIELD_OFFSET];
+static TWO_REFERENCES: [i64; 3] = [0, 1, LAST_FIELD_OFFSET];
+// The referent field is in the reference map, but is skipped as a weak field.
+static WEAK_REFERENT: [i64; 2] = [MOCK_WEAK_REF_FIELD_OFFSET as i64, LAST_FIELD_OFFSET];
+
+/// The thread of the mutators bound without one.
+static THREADLESS: OnceCell<usize> = OnceCell::new();
+
+/// A mutator thread of the mock runtime. The `tls` of a mutator is the address of its
+/// `MockMutatorThread`, so it must not move while it is in use.
+#[repr(C)]
+pub struct MockMutatorThread {
+    /// The mutator bound for this thread, if any.
+    pub mutator: *mut Mutator<ScalaNative>,
+    pub stack: Vec<usize>,
+    pub regs: Vec<usize>,
+    pub tls_block: Vec<usize>,
+    pub extra_stacks: Vec<Vec<usize>>,
+    /// The number of GCs this thread has waited for in `block_for_gc`.
+    pub gcs_seen: AtomicUsize,
+    /// The number of times the GC scanned the stack of this thread.
+    pub stack_scans: AtomicUsize,
+}
+
+impl MockMutatorThread {
+    pub fn new() -> Box<Self> {
+        Box::new(Self {
+            mutator: std::ptr::null_mut(),
+            stack: vec![0; 1],
+            regs: vec![0; 1],
+            tls_block: vec![],
+            extra_stacks: vec![],
+            gcs_seen: AtomicUsize::new(*MOCK_GCS_COMPLETED.lock().unwrap()),
+            stack_scans: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn tls(&self) -> VMMutatorThread {
+        VMMutatorThread(VMThread(OpaquePointer::from_address(Address::from_ref(self))))
+    }
+
+    /// # Safety
+    /// `tls` must come from `MockMutatorThread::tls`.
+    /// Mutators bound with `VMThread::UNINITIALIZED` get a thread with nothing to scan.
+    pub unsafe fn from_tls<'a>(tls: VMMutatorThread) -> &'a MockMutatorThread {
+        if tls.0.0.to_address().is_zero() {
+            let thread = THREADLESS.get_or_init(|| Address::from_ref(Box::leak(MockMutatorThread::new())).as_usize());
+            return Address::from_usize(*thread).as_ref::<MockMutatorThread>();
+        }
+        tls.0.0.to_address().as_ref::<MockMutatorThread>()
+    }
+}
+
+/// A mock rtti. Rtti pointers must be aligned like objects.
+#[repr(C, align(16))]
+pub struct MockRtti(pub Rtti);
+
+impl MockRtti {
+    pub fn new(id: i32, size: usize, ref_map_struct: *mut i64) -> Box<Self> {
+        Box::new(Self(Rtti {
+            rt: Runtime {
+                cls: std::ptr::null_mut(),
+                #[cfg(feature = "uses_lockword")]
+                lock_word: std::ptr::null_mut(),
+                id,
+                tid: 0,
+                name: std::ptr::null_mut(),
+            },
+            size: size as i32,
+            id_range_until: id,
+            ref_map_struct,
+        }))
+    }
+
+    /// An object with one word of payload and no references.
+    pub fn plain() -> Box<Self> {
+        Self::new(MOCK_PLAIN_OBJECT_ID, std::mem::size_of::<Object>() + 8, NO_REFERENCES.as_ptr() as *mut i64)
+    }
+
+    /// An object with two reference fields.
+    pub fn holder() -> Box<Self> {
+        Self::new(MOCK_HOLDER_OBJECT_ID, std::mem::size_of::<Object>() + 16, TWO_REFERENCES.as_ptr() as *mut i64)
+    }
+
+    /// A weak reference with `fields` words of fields, the first one being its referent.
+    pub fn weak_reference(fields: usize) -> Box<Self> {
+        Self::new(MOCK_WEAK_REF_IDS_MIN, std::mem::size_of::<Object>() + 8 * fields, WEAK_REFERENT.as_ptr() as *mut i64)
+    }
+
+    pub fn as_ptr(&mut self) -> *mut Rtti {
+        &mut self.0
+    }
+}
+
+/// Number of words in the header of an object.
+pub const HEADER_WORDS: usize = std::mem::size_of::<Object>() / std::mem::size_of::<usize>();
+
+/// Lay out an object with the given rtti and fields in plain memory.
+/// The object starts at the beginning of the returned vector.
+pub fn mock_object(rtti: *mut Rtti, fields: &[usize]) -> Vec<usize> {
+    let mut words = vec![0; HEADER_WORDS];
+    words[0] = rtti as usize;
+    words.extend_from_slice(fields);
+    words
+}
+
+/// The field at `index` of `object`.
+pub fn field_of(object: Address, index: usize) -> Address {
+    unsafe { object.add((HEADER_WORDS + index) * std::mem::size_of::<usize>()).load::<Address>() }
+}
+
+/// The payload of an object allocated with `MockRtti::plain`.
+pub fn payload_of(object: Address) -> usize {
+    field_of(object, 0).as_usize()
+}
+
+fn words_range(words: &[usize]) -> (*mut *mut usize, *mut *mut usize) {
+    let start = words.as_ptr() as *mut *mut usize;
+    (start, unsafe { start.add(words.len()) })
+}
+
+/// The number of GCs that resumed the mutators. The mutators of the mock runtime only run
+/// Rust test code, so they do not need to be stopped: they acknowledge as soon as a GC asks,
+/// and `block_for_gc` waits for the next GC.
+static MOCK_GCS_COMPLETED: Mutex<usize> = Mutex::new(0);
+static MOCK_GC_COMPLETED: Condvar = Condvar::new();
+extern "C" fn stop_all_mutators(_tls: VMWorkerThread) {
+    for entry in MUTATORS.entries() {
+        SAFEPOINT.acknowledge(entry.tls);
+    }
+}
+extern "C" fn resume_mutators(_tls: VMWorkerThread) {
+    *MOCK_GCS_COMPLETED.lock().unwrap() += 1;
+    MOCK_GC_COMPLETED.notify_all();
+}
+extern "C" fn block_for_gc(tls: VMMutatorThread) {
+    let thread = unsafe { MockMutatorThread::from_tls(tls) };
+    let mut completed = MOCK_GCS_COMPLETED.lock().unwrap();
+    while *completed <= thread.gcs_seen.load(Ordering::SeqCst) {
+        completed = MOCK_GC_COMPLETED.wait(completed).unwrap();
+    }
+    thread.gcs_seen.store(*completed, Ordering::SeqCst);
+}
+extern "C" fn out_of_memory(_tls: VMThread, err_kind: AllocationError) {
+    panic!("Out of memory: {:?}", err_kind);
+}
+/// Calls of the runtime hooks, so tests can check that the binding made them.
+pub static MOCK_FINALIZERS_SCHEDULED: AtomicUsize = AtomicUsize::new(0);
+pub static MOCK_REFERENCE_HANDLER_ATTACHED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn schedule_finalizer() {
+    MOCK_FINALIZERS_SCHEDULED.fetch_add(1, Ordering::SeqCst);
+}
+extern "C" fn attach_reference_handler_thread() {
+    MOCK_REFERENCE_HANDLER_ATTACHED.store(true, Ordering::SeqCst);
+}
+extern "C" fn detach_reference_handler_thread() {
+    MOCK_REFERENCE_HANDLER_ATTACHED.store(false, Ordering::SeqCst);
+}
+
+extern "C" fn get_object_array_id() -> i32 { MOCK_OBJECT_ARRAY_ID }
+extern "C" fn get_weak_ref_ids_min() -> i32 { MOCK_WEAK_REF_IDS_MIN }
+extern "C" fn get_weak_ref_ids_max() -> i32 { MOCK_WEAK_REF_IDS_MAX }
+extern "C" fn get_weak_ref_field_offset() -> i32 { MOCK_WEAK_REF_FIELD_OFFSET }
+extern "C" fn get_array_ids_min() -> i32 { MOCK_ARRAY_IDS_MIN }
+extern "C" fn get_array_ids_max() -> i32 { MOCK_ARRAY_IDS_MAX }
+extern "C" fn get_allocation_alignment() -> libc::size_t { MOCK_ALLOCATION_ALIGNMENT }
+
+extern "C" fn get_stack_range(tls: VMMutatorThread) -> StackRange {
+    let thread = unsafe { MockMutatorThread::from_tls(tls) };
+    thread.stack_scans.fetch_add(1, Ordering::SeqCst);
+    let (start, end) = words_range(&thread.stack);
+    // The bottom of the stack is inclusive.
+    StackRange { stack_top: start, stack_bottom: unsafe { end.offset(-1) } }
+}
+
+extern "C" fn get_regs_range(tls: VMMutatorThread) -> RegsRange {
+    let thread = unsafe { MockMutatorThread::from_tls(tls) };
+    let (start, _) = words_range(&thread.regs);
+    RegsRange { regs: start, regs_size: thread.regs.len() - 1 }
+}
+
+extern "C" fn get_extra_ranges(tls: VMMutatorThread, closure: RangesClosure) {
+    let thread = unsafe { MockMutatorThread::from_tls(tls) };
+    let (start, end) = words_range(&thread.tls_block);
+    (closure.func)(start, end, &closure.data);
+    for stack in thread.extra_stacks.iter() {
+        let (start, end) = words_range(stack);
+        (closure.func)(start, end, &closure.data);
+    }
+}
+
+pub const MOCK_MODULES_SIZE: usize = 4;
+static mut MOCK_MODULES: [*mut word_t; MOCK_MODULES_SIZE] = [std::ptr::null_mut(); MOCK_MODULES_SIZE];
+extern "C" fn get_modules() -> *mut *mut word_t { unsafe { std::ptr::addr_of_mut!(MOCK_MODULES) as *mut *mut word_t } }
+extern "C" fn get_modules_size() -> i32 { MOCK_MODULES_SIZE as i32 }
+
+/// Store `object` in the module slot `index`. The GC reads and updates the module slots.
+pub fn set_mock_module(index: usize, object: Address) {
+    unsafe { MOCK_MODULES[index] = object.to_mut_ptr() };
+}
+
+pub fn mock_module(index: usize) -> Address {
+    Address::from_mut_ptr(unsafe { MOCK_MODULES[index] })
+}
+extern "C" fn scan_roots_in_all_mutator_threads(_closure: NodesClosure) {}
+extern "C" fn scan_roots_in_mutator_thread(_closure: NodesClosure, _tls: VMMutatorThread) {}
+extern "C" fn scan_vm_specific_roots(_closure: NodesClosure) {}
+extern "C" fn prepare_for_roots_re_scanning() {}
+extern "C" fn sync_weak_ref_stack(_stack: *const *mut Object, _len: usize) {}
+extern "C" fn weak_ref_stack_nullify() {}
+extern "C" fn weak_ref_stack_call_handlers() {}
+
+extern "C" fn init_gc_worker_thread(_tls: *mut GCThreadTLS, _ctx: SendCtxPtr) {}
+extern "C" fn get_gc_thread_tls() -> *mut GCThreadTLS { std::ptr::null_mut() }
+extern "C" fn init_synchronizer_thread() {}
+
+pub static MOCK_UPCALLS: ScalaNativeUpcalls = ScalaNativeUpcalls {
+    stop_all_mutators,
+    resume_mutators,
+    block_for_gc,
+    out_of_memory,
+    schedule_finalizer,
+    attach_reference_handler_thread,
+    detach_reference_handler_thread,
+    get_object_array_id,
+    get_weak_ref_ids_min,
+    get_weak_ref_ids_max,
+    get_weak_ref_field_offset,
+    get_array_ids_min,
+    get_array_ids_max,
+    get_allocation_alignment,
+    get_stack_range,
+    get_regs_range,
+    get_extra_ranges,
+    get_modules,
+    get_modules_size,
+    scan_roots_in_all_mutator_threads,
+    scan_roots_in_mutator_thread,
+    scan_vm_specific_roots,
+    prepare_for_roots_re_scanning,
+    sync_weak_ref_stack,
+    weak_ref_stack_nullify,
+    weak_ref_stack_call_handlers,
+    init_gc_worker_thread,
+    get_gc_thread_tls,
+    init_synchronizer_thread,
+};
+
+static INSTALL: Once = Once::new();
+
+/// Point `UPCALLS` to the mock runtime. This must happen before anything reads the upcalls.
+pub fn install_mock_upcalls() {
+    INSTALL.call_once(|| unsafe { UPCALLS = &MOCK_UPCALLS });
+}
@@ -13,6 +13,8 @@ use crate::api::*;
 use crate::object_model::OBJECT_REF_OFFSET;
 use crate::ScalaNative;
 
+pub mod mock_vm;
+
 pub trait FixtureContent {
     fn create() -> Self;
 }
@@ -166,3 +168,62 @@ impl FixtureContent for MutatorFixture {
 }
 
 unsafe impl Send for MutatorFixture {}
+
+lazy_static! {
+    /// Shared by the tests running GCs on the mock runtime, as MMTk can only be initialized once.
+    /// The tests share one mutator, so they run one at a time.
+    pub static ref MOCK_GC: SerialFixture<MockGCFixture> = SerialFixture::new();
+}
+
+/// MMTk running on the mock runtime, with one mutator that can trigger GCs.
+/// The stack of the mock mutator holds no references, so objects must be kept alive by other roots.
+/// The heap is 8 MB, or `MOCK_GC_HEAP_MB` megabytes if that variable is set, e.g. for benchmarks.
+pub struct MockGCFixture {
+    pub thread: Box<mock_vm::MockMutatorThread>,
+}
+
+impl FixtureContent for MockGCFixture {
+    fn create() -> Self {
+        const MB: usize = 1024 * 1024;
+        let heap_mb = std::env::var("MOCK_GC_HEAP_MB").map_or(8, |mb| mb.parse().expect("MOCK_GC_HEAP_MB must be a number"));
+        mock_vm::install_mock_upcalls();
+        mmtk_init(heap_mb * MB, heap_mb * MB);
+        scalanative_gc_init(&mock_vm::MOCK_UPCALLS);
+        mmtk_init_binding(&mock_vm::MOCK_UPCALLS);
+        mmtk_initialize_collection(VMThread::UNINITIALIZED);
+        let mut thread = mock_vm::MockMutatorThread::new();
+        thread.mutator = mmtk_bind_mutator(thread.tls());
+
+        MockGCFixture { thread }
+    }
+}
+
+unsafe impl Send for MockGCFixture {}
+
+impl MockGCFixture {
+    /// Allocate an object with `rtti` and `fields`. `rtti` must describe an object of this size.
+    pub fn alloc(&self, rtti: *mut crate::abi::Rtti, fields: &[usize]) -> ObjectReference {
+        let words = mock_vm::mock_object(rtti, fields);
+        let size = words.len() * std::mem::size_of::<usize>();
+        let size = (size + mock_vm::MOCK_ALLOCATION_ALIGNMENT - 1) & !(mock_vm::MOCK_ALLOCATION_ALIGNMENT - 1);
+        let semantics = AllocationSemantics::Default;
+
+        let addr = mmtk_alloc(self.thread.mutator, size, mock_vm::MOCK_ALLOCATION_ALIGNMENT, 0, semantics);
+        assert!(!addr.is_zero());
+        unsafe { std::ptr::copy_nonoverlapping(words.as_ptr(), addr.to_mut_ptr::<usize>(), words.len()) };
+
+        let objref = ObjectReference::from_raw_address(addr.add(OBJECT_REF_OFFSET));
+        mmtk_post_alloc(self.thread.mutator, objref, size, semantics);
+        // Like the runtime, run the handlers of a GC the allocation triggered once it returns.
+        #[cfg(not(feature = "scalanative_multithreading_enabled"))]
+        mmtk_run_pending_handlers();
+        objref
+    }
+
+    /// Run a GC and wait for it to finish. In single-threaded mode, also run its handlers.
+    pub fn collect(&self) {
+        mmtk_handle_user_collection_request(self.thread.tls());
+        #[cfg(not(feature = "scalanative_multithreading_enabled"))]
+        mmtk_run_pending_handlers();
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use mmtk::util::opaque_pointer::*;
+
+use crate::mutators::MUTATORS;
+use crate::safepoint::{Phase, SAFEPOINT};
+use crate::tests::fixtures::mock_vm::*;
+use crate::tests::fixtures::MOCK_GC;
+
+const TLS: VMWorkerThread = VMWorkerThread(VMThread::UNINITIALIZED);
+const LONG: Duration = Duration::from_secs(10);
+
+#[test]
+pub fn the_binding_keeps_working_in_a_forked_child() {
+    MOCK_GC.with_fixture(|fixture| {
+        let tls = fixture.thread.tls();
+        match unsafe { libc::fork() } {
+            -1 => panic!("fork failed: {}", std::io::Error::last_os_error()),
+            0 => {
+                let result = std::panic::catch_unwind(|| {
+                    assert!(MUTATORS.contains(tls));
+                    // The synchronizer thread was respawned in the child.
+                    assert_eq!(SAFEPOINT.phase(), Phase::Running);
+                    SAFEPOINT.request_stop(TLS).unwrap();
+                    SAFEPOINT.wait_for(Phase::Stopped, LONG).unwrap();
+                    SAFEPOINT.request_resume(TLS).unwrap();
+                    SAFEPOINT.wait_for(Phase::Running, LONG).unwrap();
+                });
+                unsafe { libc::_exit(if result.is_ok() { 0 } else { 1 }) };
+            }
+            child => {
+                let mut status = 0;
+                assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+                assert!(libc::WIFEXITED(status));
+                assert_eq!(libc::WEXITSTATUS(status), 0);
+                // The parent is not affected.
+                fixture.collect();
+            }
+        }
+    });
+}
+
+/// The GC threads do not survive fork(), so the child does not collect, and must not wait for
+/// a collection that nothing runs.
+#[test]
+pub fn a_forked_child_allocates_without_collecting() {
+    MOCK_GC.with_fixture(|fixture| match unsafe { libc::fork() } {
+        -1 => panic!("fork failed: {}", std::io::Error::last_os_error()),
+        0 => {
+            let result = std::panic::catch_unwind(|| {
+                let mut rtti = MockRtti::plain();
+                let object = fixture.alloc(rtti.as_ptr(), &[1]).to_raw_address();
+                let scans = fixture.thread.stack_scans.load(Ordering::SeqCst);
+                fixture.collect();
+                assert_eq!(fixture.thread.stack_scans.load(Ordering::SeqCst), scans);
+                assert_eq!(payload_of(object), 1);
+            });
+            unsafe { libc::_exit(if result.is_ok() { 0 } else { 1 }) };
+        }
+        child => {
+            let mut status = 0;
+            assert_eq!(unsafe { libc::waitpid(child, &mut status, 0) }, child);
+            assert!(libc::WIFEXITED(status));
+            assert_eq!(libc::WEXITSTATUS(status), 0);
+            // The parent still collects.
+            let scans = fixture.thread.stack_scans.load(Ordering::SeqCst);
+            fixture.collect();
+            assert_eq!(fixture.thread.stack_scans.load(Ordering::SeqCst), scans + 1);
+        }
+    });
+}
@@ -0,0 +1,38 @@
+use crate::gc_threads::{parse_cpu_list, worker_thread_name};
+
+#[test]
+pub fn cpu_lists_are_parsed() {
+    assert_eq!(parse_cpu_list("3"), Some(vec![3]));
+    assert_eq!(parse_cpu_list("0-3,6"), Some(vec![0, 1, 2, 3, 6]));
+    assert_eq!(parse_cpu_list(" 2 - 3 , 8 ,"), Some(vec![2, 3, 8]));
+    assert_eq!(parse_cpu_list(""), None);
+    assert_eq!(parse_cpu_list("3-1"), None);
+    assert_eq!(parse_cpu_list("0,x"), None);
+    assert_eq!(worker_thread_name(7), "MMTk Worker Thread 7");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+pub fn affinity_is_applied_to_the_calling_thread() {
+    use std::thread;
+
+    use crate::gc_threads::set_current_thread_affinity;
+
+    fn current_cpus() -> Vec<usize> {
+        unsafe {
+            let mut set: libc::cpu_set_t = std::mem::zeroed();
+            assert_eq!(libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set), 0);
+            (0..libc::CPU_SETSIZE as usize).filter(|&cpu| libc::CPU_ISSET(cpu, &set)).collect()
+        }
+    }
+
+    // Only a CPU we are allowed to run on, e.g. inside a cpuset.
+    let cpu = current_cpus()[0];
+    thread::spawn(move || {
+        set_current_thread_affinity(&[cpu]).unwrap();
+        assert_eq!(current_cpus(), vec![cpu]);
+    })
+    .join()
+    .unwrap();
+    assert!(set_current_thread_affinity(&[libc::CPU_SETSIZE as usize]).is_err());
+}
@@ -0,0 +1,50 @@
+use crate::handles::HandleTable;
+use mmtk::util::{Address, ObjectReference};
+
+fn fake_object(addr: usize) -> ObjectReference {
+    ObjectReference::from_raw_address(unsafe { Address::from_usize(addr) })
+}
+
+fn live_slots(table: &HandleTable) -> Vec<Address> {
+    let mut slots = vec![];
+    table.for_each_slot(|slot| slots.push(slot));
+    slots
+}
+
+#[test]
+pub fn new_get_free() {
+    let table = HandleTable::new();
+    let h1 = table.new_handle(fake_object(0x1000));
+    let h2 = table.new_handle(fake_object(0x2000));
+    assert_ne!(h1, h2);
+    assert_eq!(HandleTable::get(h1), fake_object(0x1000));
+    assert_eq!(HandleTable::get(h2), fake_object(0x2000));
+    assert_eq!(live_slots(&table), vec![h1, h2]);
+
+    table.free_handle(h1);
+    assert_eq!(live_slots(&table), vec![h2]);
+
+    // The freed slot is reused.
+    let h3 = table.new_handle(fake_object(0x3000));
+    assert_eq!(h3, h1);
+    assert_eq!(HandleTable::get(h3), fake_object(0x3000));
+}
+
+#[test]
+pub fn slot_update_is_visible() {
+    let table = HandleTable::new();
+    let handle = table.new_handle(fake_object(0x1000));
+    // This is what the GC does when it moves the referent.
+    unsafe { handle.store(fake_object(0x5000)) };
+    assert_eq!(HandleTable::get(handle), fake_object(0x5000));
+}
+
+#[test]
+pub fn slots_are_stable_across_chunks() {
+    let table = HandleTable::new();
+    let handles: Vec<Address> = (1..=1000).map(|i| table.new_handle(fake_object(i * 0x10))).collect();
+    for (i, handle) in handles.iter().enumerate() {
+        assert_eq!(HandleTable::get(*handle), fake_object((i + 1) * 0x10));
+    }
+    assert_eq!(live_slots(&table).len(), 1000);
+}
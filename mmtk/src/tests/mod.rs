@@ -21,4 +21,33 @@ mod malloc_ms;
 #[cfg(feature = "is_mmtk_object")]
 mod conservatism;
 mod is_in_mmtk_spaces;
+mod root_ranges;
+mod handles;
+mod extra_ranges;
+#[cfg(feature = "is_mmtk_object")]
+mod stack_chunks;
+mod weak_refs;
+mod soft_refs;
+mod reference_queue;
+mod reference_glue;
+mod finalizers;
+mod ephemerons;
+mod weak_ref_buffers;
+#[cfg(feature = "object_pinning")]
+mod pinning;
+#[cfg(feature = "object_pinning")]
+mod pin_scopes;
+#[cfg(feature = "is_mmtk_object")]
+mod precise_roots;
+#[cfg(feature = "scalanative_multithreading_enabled")]
+mod stop_mutators;
+mod safepoint;
+mod native_state;
+mod gc_threads;
+#[cfg(feature = "scalanative_multithreading_enabled")]
+mod mutator_registry;
+#[cfg(not(feature = "scalanative_multithreading_enabled"))]
+mod single_threaded;
+#[cfg(all(unix, feature = "scalanative_multithreading_enabled"))]
+mod fork;
 mod fixtures;
@@ -0,0 +1,57 @@
+use std::sync::atomic::Ordering;
+use std::sync::Barrier;
+use std::thread;
+
+use mmtk::util::Address;
+use mmtk::vm::ActivePlan;
+
+use crate::active_plan::VMActivePlan;
+use crate::api::*;
+use crate::mutators::{StackBounds, MUTATORS};
+use crate::tests::fixtures::mock_vm::*;
+use crate::tests::fixtures::MOCK_GC;
+
+#[test]
+pub fn foreign_threads_attach_and_detach() {
+    MOCK_GC.with_fixture(|fixture| {
+        assert!(VMActivePlan::is_mutator(fixture.thread.tls().0));
+        assert_eq!(VMActivePlan::mutator(fixture.thread.tls()) as *mut _, fixture.thread.mutator);
+        let mutators = VMActivePlan::number_of_mutators();
+
+        let attached = Barrier::new(2);
+        let collected = Barrier::new(2);
+        thread::scope(|scope| {
+            let foreign = scope.spawn(|| {
+                let thread = MockMutatorThread::new();
+                let mutator = mmtk_attach_current_thread(thread.tls());
+                assert_eq!(mmtk_attach_current_thread(thread.tls()), mutator);
+                assert_eq!(MUTATORS.attached_to_current_thread(), Some(mutator));
+
+                let mut bounds = StackBounds { low: Address::ZERO, high: Address::ZERO };
+                assert!(mmtk_get_mutator_stack_bounds(mutator, &mut bounds));
+                #[cfg(target_os = "linux")]
+                assert!(bounds.contains(Address::from_ref(&bounds)));
+
+                attached.wait();
+                collected.wait();
+                // The stack of the attached thread was scanned by the GC. Every GC runs under
+                // MOCK_GC, so no other test collected while the thread was attached.
+                assert_eq!(thread.stack_scans.load(Ordering::SeqCst), 1);
+                assert!(mmtk_detach_current_thread());
+                assert!(!mmtk_detach_current_thread());
+                assert!(!VMActivePlan::is_mutator(thread.tls().0));
+            });
+            attached.wait();
+            assert_eq!(VMActivePlan::number_of_mutators(), mutators + 1);
+            fixture.collect();
+            collected.wait();
+            foreign.join().unwrap();
+        });
+        assert_eq!(VMActivePlan::number_of_mutators(), mutators);
+
+        // The mutators bound by the runtime have no recorded stack.
+        let mut bounds = StackBounds { low: Address::ZERO, high: Address::ZERO };
+        assert!(!mmtk_get_mutator_stack_bounds(fixture.thread.mutator, &mut bounds));
+        assert!(MUTATORS.attached_to_current_thread().is_none());
+    });
+}
@@ -0,0 +1,100 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use mmtk::util::opaque_pointer::*;
+
+use crate::mutators::{MutatorRegistry, MutatorState};
+use crate::safepoint::{HandshakeError, Phase, Safepoint};
+use crate::tests::fixtures::mock_vm::MockMutatorThread;
+
+const TLS: VMWorkerThread = VMWorkerThread(VMThread::UNINITIALIZED);
+const LONG: Duration = Duration::from_secs(10);
+
+#[test]
+pub fn native_threads_block_on_exit_while_stopped() {
+    let safepoint: &'static Safepoint = Box::leak(Box::new(Safepoint::new()));
+    let mutators: &'static MutatorRegistry = Box::leak(Box::new(MutatorRegistry::new()));
+    let mutator = MockMutatorThread::new();
+    let mutator_tls = mutator.tls();
+    mutators.register(mutator_tls, std::ptr::null_mut());
+    assert_eq!(mutators.state(mutator_tls), Some(MutatorState::Managed));
+
+    let synchronizer = thread::spawn(move || safepoint.serve(|_| {}, |_| {}, || mutators.managed()));
+    while safepoint.request_stop(TLS) == Err(HandshakeError::NotServed) {
+        thread::yield_now();
+    }
+    safepoint.acknowledge(mutator_tls);
+    safepoint.wait_for(Phase::Stopped, LONG).unwrap();
+    safepoint.request_resume(TLS).unwrap();
+    safepoint.wait_for(Phase::Running, LONG).unwrap();
+
+    // Entering native state while a stop is requested counts as reaching the safepoint.
+    safepoint.request_stop(TLS).unwrap();
+    mutators.enter_native(mutator_tls, safepoint);
+    assert!(mutators.is_native(mutator_tls));
+    safepoint.wait_for(Phase::Stopped, LONG).unwrap();
+    assert!(safepoint.is_acknowledged(mutator_tls));
+
+    // Leaving native state waits for the GC to finish.
+    let exited: &'static AtomicBool = Box::leak(Box::new(AtomicBool::new(false)));
+    let native_call = thread::spawn(move || {
+        mutators.exit_native(mutator_tls, safepoint);
+        exited.store(true, Ordering::SeqCst);
+    });
+    thread::sleep(Duration::from_millis(50));
+    assert!(!exited.load(Ordering::SeqCst));
+    assert!(mutators.is_native(mutator_tls));
+
+    safepoint.request_resume(TLS).unwrap();
+    native_call.join().unwrap();
+    assert!(exited.load(Ordering::SeqCst));
+    assert_eq!(mutators.state(mutator_tls), Some(MutatorState::Managed));
+
+    // Without a GC in progress, the thread leaves native state immediately.
+    mutators.enter_native(mutator_tls, safepoint);
+    mutators.exit_native(mutator_tls, safepoint);
+    assert_eq!(mutators.state(mutator_tls), Some(MutatorState::Managed));
+
+    mutators.unregister(std::ptr::null_mut());
+    assert_eq!(mutators.state(mutator_tls), None);
+    safepoint.shutdown();
+    synchronizer.join().unwrap();
+}
+
+#[test]
+pub fn gc_does_not_wait_for_native_threads() {
+    let safepoint: &'static Safepoint = Box::leak(Box::new(Safepoint::new()));
+    let mutators: &'static MutatorRegistry = Box::leak(Box::new(MutatorRegistry::new()));
+    let managed = MockMutatorThread::new();
+    let native = MockMutatorThread::new();
+    let (managed_tls, native_tls) = (managed.tls(), native.tls());
+    // The registry only tells the mutators apart, so any distinct pointers do.
+    mutators.register(managed_tls, managed_tls.0.0.to_address().to_mut_ptr());
+    mutators.register(native_tls, native_tls.0.0.to_address().to_mut_ptr());
+    // The thread entered a blocking call before the GC was requested, so it never acknowledges.
+    mutators.enter_native(native_tls, safepoint);
+    assert_eq!(mutators.managed(), vec![managed_tls]);
+
+    let synchronizer = thread::spawn(move || safepoint.serve(|_| {}, |_| {}, || mutators.managed()));
+    while safepoint.request_stop(TLS) == Err(HandshakeError::NotServed) {
+        thread::yield_now();
+    }
+    // The GC waits for the managed thread...
+    assert_eq!(
+        safepoint.wait_for(Phase::Stopped, Duration::from_millis(50)),
+        Err(HandshakeError::Timeout(Phase::Stopped, Duration::from_millis(50)))
+    );
+    // ...but not for the native one.
+    safepoint.acknowledge(managed_tls);
+    safepoint.wait_for(Phase::Stopped, LONG).unwrap();
+    assert!(!safepoint.is_acknowledged(native_tls));
+
+    safepoint.request_resume(TLS).unwrap();
+    safepoint.wait_for(Phase::Running, LONG).unwrap();
+    mutators.exit_native(native_tls, safepoint);
+    assert_eq!(mutators.managed().len(), 2);
+
+    safepoint.shutdown();
+    synchronizer.join().unwrap();
+}
@@ -0,0 +1,70 @@
+use crate::api::*;
+use crate::pin_scopes::PIN_SCOPES;
+use crate::tests::fixtures::mock_vm::*;
+use crate::tests::fixtures::MOCK_GC;
+
+#[test]
+pub fn scopes_unpin_what_they_pinned() {
+    MOCK_GC.with_fixture(|fixture| {
+        let mut rtti = MockRtti::plain();
+        let a = fixture.alloc(rtti.as_ptr(), &[1]).to_raw_address().to_mut_ptr();
+        let b = fixture.alloc(rtti.as_ptr(), &[2]).to_raw_address().to_mut_ptr();
+        let mutator = fixture.thread.mutator;
+
+        mmtk_pin_scope_enter(mutator);
+        assert!(mmtk_pin_in_scope(mutator, a));
+        mmtk_pin_scope_enter(mutator);
+        // Already pinned by the outer scope, which stays responsible for it.
+        assert!(!mmtk_pin_in_scope(mutator, a));
+        assert!(mmtk_pin_in_scope(mutator, b));
+        assert_eq!(PIN_SCOPES.depth(mutator), 2);
+        mmtk_pin_scope_exit(mutator);
+        assert!(mmtk_is_pinned(a));
+        assert!(!mmtk_is_pinned(b));
+        mmtk_pin_scope_exit(mutator);
+        assert!(!mmtk_is_pinned(a));
+        assert_eq!(PIN_SCOPES.depth(mutator), 0);
+
+        // Objects pinned outside of any scope are not unpinned by a scope.
+        assert!(mmtk_pin_object(b));
+        mmtk_pin_scope_enter(mutator);
+        assert!(!mmtk_pin_in_scope(mutator, b));
+        mmtk_pin_scope_exit(mutator);
+        assert!(mmtk_unpin_object(b));
+
+        // A thread exiting inside scopes releases them.
+        let thread = MockMutatorThread::new();
+        let exiting = mmtk_bind_mutator(thread.tls());
+        mmtk_pin_scope_enter(exiting);
+        mmtk_pin_scope_enter(exiting);
+        assert!(mmtk_pin_in_scope(exiting, a));
+        mmtk_destroy_mutator(exiting);
+        assert!(!mmtk_is_pinned(a));
+        assert_eq!(PIN_SCOPES.depth(exiting), 0);
+    });
+}
+
+// Without stress copying, Immix may well leave the object in place either way.
+#[cfg(feature = "immix_stress_copying")]
+#[test]
+pub fn objects_pinned_in_a_scope_move_only_after_it() {
+    MOCK_GC.with_fixture(|fixture| {
+        let mut rtti = MockRtti::plain();
+        let object = fixture.alloc(rtti.as_ptr(), &[1]);
+        let handle = mmtk_handle_new(object);
+        let mutator = fixture.thread.mutator;
+
+        mmtk_pin_scope_enter(mutator);
+        assert!(mmtk_pin_in_scope(mutator, object.to_raw_address().to_mut_ptr()));
+        fixture.collect();
+        assert_eq!(mmtk_handle_get(handle), object);
+        mmtk_pin_scope_exit(mutator);
+
+        fixture.collect();
+        let moved = mmtk_handle_get(handle);
+        assert_ne!(moved, object);
+        assert_eq!(payload_of(moved.to_raw_address()), 1);
+
+        mmtk_handle_free(handle);
+    });
+}
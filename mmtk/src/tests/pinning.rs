@@ -0,0 +1,83 @@
+use crate::api::*;
+use crate::tests::fixtures::mock_vm::*;
+use crate::tests::fixtures::MOCK_GC;
+
+#[test]
+pub fn pinning_is_reported_and_outlives_a_gc() {
+    MOCK_GC.with_fixture(|fixture| {
+        let mut rtti = MockRtti::plain();
+        let pinned = fixture.alloc(rtti.as_ptr(), &[1]);
+        let unpinned = fixture.alloc(rtti.as_ptr(), &[2]);
+        let pinned_ptr = pinned.to_raw_address().to_mut_ptr();
+
+        assert!(mmtk_pin_object(pinned_ptr));
+        // Pinning twice is allowed, but reported.
+        assert!(!mmtk_pin_object(pinned_ptr));
+        assert!(mmtk_is_pinned(pinned_ptr));
+        assert!(!mmtk_is_pinned(unpinned.to_raw_address().to_mut_ptr()));
+
+        let pinned_handle = mmtk_handle_new(pinned);
+        fixture.collect();
+        assert_eq!(mmtk_handle_get(pinned_handle), pinned);
+
+        // The GC only unpins the objects it pinned itself.
+        assert!(mmtk_is_pinned(pinned_ptr));
+        assert!(mmtk_unpin_object(pinned_ptr));
+        assert!(!mmtk_unpin_object(pinned_ptr));
+        assert!(!mmtk_is_pinned(pinned_ptr));
+
+        mmtk_handle_free(pinned_handle);
+    });
+}
+
+// Without stress copying, Immix may well leave both objects in place, and the test would
+// pass whether or not pinning works.
+#[cfg(feature = "immix_stress_copying")]
+#[test]
+pub fn pinned_object_survives_copying_gc_in_place() {
+    MOCK_GC.with_fixture(|fixture| {
+        let mut rtti = MockRtti::plain();
+        let pinned = fixture.alloc(rtti.as_ptr(), &[1]);
+        let unpinned = fixture.alloc(rtti.as_ptr(), &[2]);
+        assert!(mmtk_pin_object(pinned.to_raw_address().to_mut_ptr()));
+
+        let pinned_handle = mmtk_handle_new(pinned);
+        let unpinned_handle = mmtk_handle_new(unpinned);
+        fixture.collect();
+
+        assert_eq!(mmtk_handle_get(pinned_handle), pinned);
+        assert_eq!(payload_of(pinned.to_raw_address()), 1);
+        // The control object must have moved, or the GC did not copy at all.
+        let unpinned_after = mmtk_handle_get(unpinned_handle);
+        assert_ne!(unpinned_after, unpinned);
+        assert_eq!(payload_of(unpinned_after.to_raw_address()), 2);
+
+        assert!(mmtk_unpin_object(pinned.to_raw_address().to_mut_ptr()));
+        mmtk_handle_free(pinned_handle);
+        mmtk_handle_free(unpinned_handle);
+    });
+}
+
+// A second mutator, whose stack refers to the object, needs a multi-threaded runtime.
+#[cfg(feature = "scalanative_multithreading_enabled")]
+#[test]
+pub fn objects_found_on_a_stack_are_pinned_during_the_gc() {
+    MOCK_GC.with_fixture(|fixture| {
+        let mut rtti = MockRtti::plain();
+        let on_stack = fixture.alloc(rtti.as_ptr(), &[1]).to_raw_address();
+        let mut thread = MockMutatorThread::new();
+        thread.stack = vec![on_stack.as_usize()];
+        let mutator = mmtk_bind_mutator(thread.tls());
+
+        fixture.collect();
+
+        // The stack word is never updated, so the object must still be there, not forwarded,
+        // even when every unpinned object is copied.
+        assert_eq!(unsafe { on_stack.load::<usize>() }, rtti.as_ptr() as usize);
+        assert_eq!(payload_of(on_stack), 1);
+        // The GC unpinned it once it was over.
+        assert!(!mmtk_is_pinned(on_stack.to_mut_ptr()));
+
+        mmtk_destroy_mutator(mutator);
+    });
+}
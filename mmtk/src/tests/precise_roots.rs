@@ -0,0 +1,66 @@
+use mmtk::util::Address;
+
+use crate::api::*;
+use crate::tests::fixtures::mock_vm::*;
+use crate::tests::fixtures::MOCK_GC;
+
+#[test]
+pub fn precise_roots_move_and_ambiguous_roots_stay() {
+    MOCK_GC.with_fixture(|fixture| {
+        let mut rtti = MockRtti::plain();
+        let precise = fixture.alloc(rtti.as_ptr(), &[1]).to_raw_address();
+        let ambiguous = fixture.alloc(rtti.as_ptr(), &[2]).to_raw_address();
+
+        set_mock_module(0, precise);
+        // Root ranges registered by native code are scanned conservatively.
+        let range = Box::new([ambiguous.as_usize()]);
+        let start = Address::from_ptr(range.as_ptr());
+        let end = start + std::mem::size_of_val(&*range);
+        mmtk_add_roots(start, end);
+
+        fixture.collect();
+
+        let precise_after = mock_module(0);
+        assert!(mmtk_is_mmtk_object(precise_after));
+        assert_eq!(payload_of(precise_after), 1);
+        #[cfg(feature = "immix_stress_copying")]
+        assert_ne!(precise_after, precise);
+        // An ambiguous root cannot be updated, so its object must stay in place.
+        assert_eq!(range[0], ambiguous.as_usize());
+        assert!(mmtk_is_mmtk_object(ambiguous));
+        assert_eq!(payload_of(ambiguous), 2);
+
+        mmtk_remove_roots(start, end);
+        set_mock_module(0, Address::ZERO);
+    });
+}
+
+/// Tracing a module slot does not visit the lock word of its object, so the monitor is
+/// only kept alive because the module scan reports it.
+#[cfg(feature = "uses_lockword")]
+#[test]
+pub fn inflated_monitors_of_module_objects_survive_a_gc() {
+    use crate::abi::{field_alligned_lock_ref, field_inflate_lock_ref, field_is_inflated_lock, Field_t, Object};
+
+    MOCK_GC.with_fixture(|fixture| {
+        let mut rtti = MockRtti::plain();
+        let object = fixture.alloc(rtti.as_ptr(), &[1]).to_raw_address();
+        let monitor = fixture.alloc(rtti.as_ptr(), &[2]).to_raw_address();
+        unsafe { (*object.to_mut_ptr::<Object>()).lock_word = field_inflate_lock_ref(monitor.to_mut_ptr()) };
+        set_mock_module(0, object);
+
+        fixture.collect();
+
+        let object = mock_module(0);
+        assert_eq!(payload_of(object), 1);
+        let lock_word: Field_t = unsafe { (*object.to_mut_ptr::<Object>()).lock_word };
+        assert!(field_is_inflated_lock(lock_word));
+        // Monitors reported as roots are pinned, so the lock word needs no update.
+        let monitor_after = Address::from_mut_ptr(field_alligned_lock_ref(lock_word));
+        assert_eq!(monitor_after, monitor);
+        assert!(mmtk_is_mmtk_object(monitor_after));
+        assert_eq!(payload_of(monitor_after), 2);
+
+        set_mock_module(0, Address::ZERO);
+    });
+}
@@ -0,0 +1,68 @@
+use std::sync::Mutex;
+
+use mmtk::util::{Address, ObjectReference};
+use mmtk::vm::ReferenceGlue;
+
+use crate::abi::Object;
+use crate::api::*;
+use crate::reference_glue::VMReferenceGlue;
+use crate::reference_handler::REFERENCE_HANDLER;
+use crate::tests::fixtures::mock_vm::*;
+use crate::tests::fixtures::MOCK_GC;
+
+static RECEIVED: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+extern "C" fn handler(references: *const *mut Object, len: usize) {
+    let batch = unsafe { std::slice::from_raw_parts(references, len) };
+    RECEIVED.lock().unwrap().extend(batch.iter().map(|r| *r as usize));
+}
+
+/// With `mmtk_reference_processor`, the references are registered with MMTk, which clears
+/// them and hands them over through `VMReferenceGlue::enqueue_references`. Otherwise the
+/// binding discovers them while tracing. Either way, the runtime sees the same outcome.
+#[test]
+pub fn cleared_referents_read_as_null_and_reach_the_queue_handler() {
+    MOCK_GC.with_fixture(|fixture| {
+        REFERENCE_HANDLER.wait_until_idle();
+        RECEIVED.lock().unwrap().clear();
+        mmtk_set_reference_queue_handler(Some(handler));
+        let mut plain_rtti = MockRtti::plain();
+        let mut weak_rtti = MockRtti::weak_reference(1);
+        let mut holder_rtti = MockRtti::holder();
+
+        let live = fixture.alloc(plain_rtti.as_ptr(), &[1]);
+        let dead = fixture.alloc(plain_rtti.as_ptr(), &[2]);
+        let to_live = fixture.alloc(weak_rtti.as_ptr(), &[live.to_raw_address().as_usize()]);
+        let to_dead = fixture.alloc(weak_rtti.as_ptr(), &[dead.to_raw_address().as_usize()]);
+        // Weak references are discovered when they are reached through a field.
+        let holder = fixture.alloc(holder_rtti.as_ptr(), &[to_live.to_raw_address().as_usize(), to_dead.to_raw_address().as_usize()]);
+        #[cfg(feature = "mmtk_reference_processor")]
+        {
+            mmtk_add_weak_candidate(to_live);
+            mmtk_add_weak_candidate(to_dead);
+        }
+        assert_eq!(VMReferenceGlue::get_referent(to_live), live);
+        assert_eq!(VMReferenceGlue::get_referent(to_dead), dead);
+        set_mock_module(0, holder.to_raw_address());
+        set_mock_module(1, live.to_raw_address());
+
+        fixture.collect();
+        REFERENCE_HANDLER.wait_until_idle();
+
+        let holder = mock_module(0);
+        let to_live = ObjectReference::from_raw_address(field_of(holder, 0));
+        let to_dead = ObjectReference::from_raw_address(field_of(holder, 1));
+        assert_eq!(VMReferenceGlue::get_referent(to_live).to_raw_address(), mock_module(1));
+        assert_eq!(payload_of(mock_module(1)), 1);
+        assert!(VMReferenceGlue::get_referent(to_dead).is_null());
+        // Only the cleared reference is enqueued, at its address after the GC.
+        assert_eq!(*RECEIVED.lock().unwrap(), vec![to_dead.to_raw_address().as_usize()]);
+
+        VMReferenceGlue::set_referent(to_live, ObjectReference::NULL);
+        assert!(VMReferenceGlue::get_referent(to_live).is_null());
+
+        mmtk_set_reference_queue_handler(None);
+        set_mock_module(0, Address::ZERO);
+        set_mock_module(1, Address::ZERO);
+    });
+}
@@ -0,0 +1,100 @@
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use mmtk::util::{Address, ObjectReference};
+
+use crate::abi::Object;
+use crate::api::mmtk_set_reference_queue_handler;
+use crate::reference_handler::{ReferenceHandler, REFERENCE_HANDLER};
+use crate::scanning::{mmtk_enqueue_cleared_references, record_cleared_references, seal_cleared_references, HANDLER_FN};
+use crate::tests::fixtures::mock_vm::*;
+use crate::tests::fixtures::MOCK_GC;
+
+// The cleared references and the handlers are shared with the GCs run by other tests, so
+// these tests run under `MOCK_GC` to be serialized with them, once the global reference
+// handler thread is done with the last GC.
+
+static RECEIVED: Mutex<Vec<Vec<usize>>> = Mutex::new(Vec::new());
+/// The threads the handlers ran on, and whether they were attached to the runtime.
+static HANDLER_THREADS: Mutex<Vec<(Option<String>, bool)>> = Mutex::new(Vec::new());
+
+fn record_handler_thread() {
+    let attached = MOCK_REFERENCE_HANDLER_ATTACHED.load(Ordering::SeqCst);
+    HANDLER_THREADS.lock().unwrap().push((std::thread::current().name().map(str::to_string), attached));
+}
+
+extern "C" fn handler(references: *const *mut Object, len: usize) {
+    let batch = unsafe { std::slice::from_raw_parts(references, len) };
+    RECEIVED.lock().unwrap().push(batch.iter().map(|r| *r as usize).collect());
+    record_handler_thread();
+}
+
+fn objref(addr: usize) -> ObjectReference {
+    ObjectReference::from_raw_address(unsafe { Address::from_usize(addr) })
+}
+
+#[test]
+pub fn cleared_references_are_handed_over_in_one_batch() {
+    MOCK_GC.with_fixture(|_| {
+        REFERENCE_HANDLER.wait_until_idle();
+        RECEIVED.lock().unwrap().clear();
+        mmtk_set_reference_queue_handler(Some(handler));
+
+        // What two GCs do: the references cleared by each one are one batch.
+        record_cleared_references(&[objref(0x1000), objref(0x2000)]);
+        record_cleared_references(&[objref(0x3000)]);
+        seal_cleared_references();
+        record_cleared_references(&[objref(0x4000)]);
+        seal_cleared_references();
+        mmtk_enqueue_cleared_references();
+        // Nothing is cleared, so the handler is not called.
+        seal_cleared_references();
+        mmtk_enqueue_cleared_references();
+
+        assert_eq!(*RECEIVED.lock().unwrap(), vec![vec![0x1000, 0x2000, 0x3000], vec![0x4000]]);
+        mmtk_set_reference_queue_handler(None);
+    });
+}
+
+#[test]
+pub fn handlers_run_on_the_reference_handler_thread() {
+    // Not the global `REFERENCE_HANDLER`, which must keep running for the other tests.
+    static HANDLER: ReferenceHandler = ReferenceHandler::new();
+    MOCK_GC.with_fixture(|_| {
+        REFERENCE_HANDLER.wait_until_idle();
+        RECEIVED.lock().unwrap().clear();
+        HANDLER_THREADS.lock().unwrap().clear();
+        mmtk_set_reference_queue_handler(Some(handler));
+        *HANDLER_FN.lock().unwrap() = Some(record_handler_thread);
+        let finalizers_scheduled = MOCK_FINALIZERS_SCHEDULED.load(Ordering::SeqCst);
+
+        // What a GC does before resuming the mutators.
+        record_cleared_references(&[objref(0x1000)]);
+        seal_cleared_references();
+        HANDLER.wake(true);
+        HANDLER.wait_until_idle();
+
+        assert_eq!(*RECEIVED.lock().unwrap(), vec![vec![0x1000]]);
+        assert_eq!(MOCK_FINALIZERS_SCHEDULED.load(Ordering::SeqCst), finalizers_scheduled + 1);
+        // The handlers ran attached, and the thread waits for the next GC detached.
+        let handler_thread = (Some("Reference Handler".to_string()), true);
+        assert_eq!(*HANDLER_THREADS.lock().unwrap(), vec![handler_thread.clone(), handler_thread]);
+        assert!(!MOCK_REFERENCE_HANDLER_ATTACHED.load(Ordering::SeqCst));
+
+        // A GC that cleared nothing still runs the weak reference handler, but nothing else.
+        HANDLER.wake(false);
+        HANDLER.wait_until_idle();
+        assert_eq!(RECEIVED.lock().unwrap().len(), 1);
+        assert_eq!(HANDLER_THREADS.lock().unwrap().len(), 3);
+        assert_eq!(MOCK_FINALIZERS_SCHEDULED.load(Ordering::SeqCst), finalizers_scheduled + 1);
+
+        HANDLER.shutdown();
+        // The handlers are not run after the shutdown.
+        HANDLER.wake(true);
+        HANDLER.wait_until_idle();
+        assert_eq!(HANDLER_THREADS.lock().unwrap().len(), 3);
+
+        *HANDLER_FN.lock().unwrap() = None;
+        mmtk_set_reference_queue_handler(None);
+    });
+}
@@ -0,0 +1,53 @@
+use crate::roots::RootRanges;
+
+fn collect(ranges: &RootRanges) -> Vec<(usize, usize)> {
+    let mut result = vec![];
+    ranges.for_each(|start, end| result.push((start, end)));
+    result
+}
+
+#[test]
+pub fn add_and_remove() {
+    let ranges = RootRanges::new();
+    ranges.add(0x1000, 0x2000);
+    ranges.add(0x4000, 0x4100);
+    assert_eq!(collect(&ranges), vec![(0x1000, 0x2000), (0x4000, 0x4100)]);
+
+    ranges.remove(0x1000, 0x2000);
+    assert_eq!(collect(&ranges), vec![(0x4000, 0x4100)]);
+}
+
+#[test]
+pub fn add_same_start_extends() {
+    let ranges = RootRanges::new();
+    ranges.add(0x1000, 0x2000);
+    ranges.add(0x1000, 0x3000);
+    ranges.add(0x1000, 0x1800);
+    assert_eq!(collect(&ranges), vec![(0x1000, 0x3000)]);
+}
+
+#[test]
+pub fn add_aligns_to_words() {
+    let ranges = RootRanges::new();
+    ranges.add(0x1001, 0x2007);
+    assert_eq!(collect(&ranges), vec![(0x1008, 0x2000)]);
+
+    // Ranges smaller than a word are ignored.
+    ranges.add(0x3001, 0x3007);
+    assert_eq!(ranges.len(), 1);
+}
+
+#[test]
+pub fn remove_only_contained_ranges() {
+    let ranges = RootRanges::new();
+    ranges.add(0x1000, 0x2000);
+    ranges.add(0x2000, 0x3000);
+    ranges.add(0x3000, 0x4000);
+
+    // Only partially covers the first and the last range.
+    ranges.remove(0x1800, 0x3800);
+    assert_eq!(collect(&ranges), vec![(0x1000, 0x2000), (0x3000, 0x4000)]);
+
+    ranges.remove(0x0, 0x10000);
+    assert!(ranges.is_empty());
+}
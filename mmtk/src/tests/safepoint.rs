@@ -0,0 +1,143 @@
+use std::cell::RefCell;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use mmtk::util::opaque_pointer::*;
+
+use crate::safepoint::{HandshakeError, Phase, Safepoint};
+use crate::tests::fixtures::mock_vm::MockMutatorThread;
+
+const TLS: VMWorkerThread = VMWorkerThread(VMThread::UNINITIALIZED);
+const LONG: Duration = Duration::from_secs(10);
+
+#[test]
+pub fn handshake_stops_and_resumes_the_mutators() {
+    let safepoint: &'static Safepoint = Box::leak(Box::new(Safepoint::new()));
+    assert_eq!(safepoint.request_stop(TLS), Err(HandshakeError::NotServed));
+
+    let events: &'static Mutex<Vec<&str>> = Box::leak(Box::new(Mutex::new(Vec::new())));
+    let synchronizer = thread::spawn(move || {
+        safepoint.serve(
+            |_| {
+                // Mutators that take a while to reach a safepoint.
+                thread::sleep(Duration::from_millis(100));
+                events.lock().unwrap().push("stopped");
+            },
+            |_| events.lock().unwrap().push("resumed"),
+            Vec::new,
+        );
+    });
+    while safepoint.request_stop(TLS) == Err(HandshakeError::NotServed) {
+        thread::yield_now();
+    }
+
+    // The GC cannot start before the mutators are stopped.
+    assert_eq!(
+        safepoint.wait_for(Phase::Stopped, Duration::from_millis(1)),
+        Err(HandshakeError::Timeout(Phase::Stopped, Duration::from_millis(1)))
+    );
+    assert!(events.lock().unwrap().is_empty());
+    // The request is still pending after a timeout.
+    assert_eq!(safepoint.wait_for(Phase::Stopped, LONG), Ok(()));
+    assert_eq!(*events.lock().unwrap(), vec!["stopped"]);
+    assert_eq!(safepoint.request_stop(TLS), Err(HandshakeError::InvalidPhase(Phase::Stopped)));
+
+    safepoint.request_resume(TLS).unwrap();
+    assert_eq!(safepoint.wait_for(Phase::Running, LONG), Ok(()));
+    assert_eq!(*events.lock().unwrap(), vec!["stopped", "resumed"]);
+    assert_eq!(safepoint.request_resume(TLS), Err(HandshakeError::InvalidPhase(Phase::Running)));
+
+    safepoint.shutdown();
+    synchronizer.join().unwrap();
+    assert_eq!(safepoint.request_stop(TLS), Err(HandshakeError::ShutDown));
+}
+
+#[test]
+pub fn time_to_safepoint_is_measured() {
+    let safepoint: &'static Safepoint = Box::leak(Box::new(Safepoint::new()));
+    let mutator = MockMutatorThread::new();
+    let mutator_tls = mutator.tls();
+    let mutator_address = mutator_tls.0.0.to_address().as_usize();
+    let synchronizer = thread::spawn(move || {
+        safepoint.serve(
+            |_| {
+                thread::sleep(Duration::from_millis(5));
+                // The mutator reaches its safepoint.
+                safepoint.acknowledge(mutator_tls);
+            },
+            |_| {},
+            move || vec![mutator_tls],
+        );
+    });
+    while safepoint.request_stop(TLS) == Err(HandshakeError::NotServed) {
+        thread::yield_now();
+    }
+    assert!(!safepoint.is_acknowledged(mutator_tls));
+    safepoint.wait_for(Phase::Stopped, LONG).unwrap();
+    assert!(safepoint.is_acknowledged(mutator_tls));
+    let acknowledgements = safepoint.acknowledgements();
+    assert_eq!(acknowledgements.len(), 1);
+    assert_eq!(acknowledgements[0].0, mutator_address);
+    assert!(acknowledgements[0].1 >= Duration::from_millis(5));
+    safepoint.request_resume(TLS).unwrap();
+    safepoint.wait_for(Phase::Running, LONG).unwrap();
+
+    let stats = safepoint.stats();
+    assert_eq!(stats.count, 1);
+    assert!(stats.last_ns >= 5_000_000);
+    assert_eq!(stats.max_ns, stats.last_ns);
+    assert_eq!(stats.total_ns, stats.last_ns);
+    assert!(stats.last_pause_ns >= stats.last_ns);
+    // 5ms and more fall in the buckets from [4096us, 8192us) on.
+    let histogram = safepoint.histogram();
+    assert_eq!(histogram.iter().sum::<u64>(), 1);
+    assert!(histogram[..13].iter().all(|&count| count == 0));
+
+    safepoint.shutdown();
+    synchronizer.join().unwrap();
+}
+
+#[test]
+pub fn shutdown_waits_for_the_mutators_to_resume() {
+    let safepoint: &'static Safepoint = Box::leak(Box::new(Safepoint::new()));
+    let synchronizer = thread::spawn(move || safepoint.serve(|_| {}, |_| {}, Vec::new));
+    while safepoint.request_stop(TLS) == Err(HandshakeError::NotServed) {
+        thread::yield_now();
+    }
+    safepoint.wait_for(Phase::Stopped, LONG).unwrap();
+
+    let shutdown = thread::spawn(move || safepoint.shutdown());
+    thread::sleep(Duration::from_millis(50));
+    assert!(!shutdown.is_finished());
+    assert_eq!(safepoint.phase(), Phase::Stopped);
+
+    safepoint.request_resume(TLS).unwrap();
+    shutdown.join().unwrap();
+    synchronizer.join().unwrap();
+    assert_eq!(safepoint.phase(), Phase::Running);
+    assert_eq!(safepoint.request_stop(TLS), Err(HandshakeError::ShutDown));
+}
+
+#[test]
+pub fn requests_after_shutdown_are_served_in_place() {
+    let safepoint: &'static Safepoint = Box::leak(Box::new(Safepoint::new()));
+    let synchronizer = thread::spawn(move || safepoint.serve(|_| {}, |_| {}, Vec::new));
+    safepoint.shutdown();
+    synchronizer.join().unwrap();
+    // A GC requested before collection was disabled.
+    assert_eq!(safepoint.request_stop(TLS), Err(HandshakeError::ShutDown));
+
+    let events = RefCell::new(Vec::new());
+    let stop = |_: VMWorkerThread| events.borrow_mut().push("stopped");
+    let resume = |_: VMWorkerThread| events.borrow_mut().push("resumed");
+    safepoint.serve_in_place(TLS, Phase::StopRequested, stop, resume, Vec::new).unwrap();
+    assert_eq!(safepoint.phase(), Phase::Stopped);
+    safepoint.serve_in_place(TLS, Phase::ResumeRequested, stop, resume, Vec::new).unwrap();
+    assert_eq!(safepoint.phase(), Phase::Running);
+    assert_eq!(*events.borrow(), vec!["stopped", "resumed"]);
+    assert_eq!(
+        safepoint.serve_in_place(TLS, Phase::ResumeRequested, stop, resume, Vec::new),
+        Err(HandshakeError::InvalidPhase(Phase::Running))
+    );
+}
@@ -0,0 +1,80 @@
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use mmtk::util::{Address, ObjectReference};
+use mmtk::vm::{ActivePlan, ReferenceGlue};
+
+use crate::abi::Object;
+use crate::active_plan::VMActivePlan;
+use crate::api::*;
+use crate::reference_glue::VMReferenceGlue;
+use crate::tests::fixtures::mock_vm::*;
+use crate::tests::fixtures::MOCK_GC;
+
+#[test]
+pub fn the_gc_runs_without_a_handshake() {
+    MOCK_GC.with_fixture(|fixture| {
+        assert_eq!(VMActivePlan::number_of_mutators(), 1);
+        assert!(VMActivePlan::is_mutator(fixture.thread.tls().0));
+        assert_eq!(VMActivePlan::mutators().count(), 1);
+
+        let scans = fixture.thread.stack_scans.load(Ordering::SeqCst);
+        fixture.collect();
+        assert_eq!(fixture.thread.stack_scans.load(Ordering::SeqCst), scans + 1);
+    });
+}
+
+static RECEIVED: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+extern "C" fn handler(references: *const *mut Object, len: usize) {
+    let batch = unsafe { std::slice::from_raw_parts(references, len) };
+    RECEIVED.lock().unwrap().extend(batch.iter().map(|r| *r as usize));
+}
+
+/// `block_for_gc` runs in the allocation slow path, so the handlers wait until the mutator
+/// reaches a safe point and calls `mmtk_run_pending_handlers`.
+#[test]
+pub fn the_mutator_runs_the_handlers_at_its_next_poll() {
+    MOCK_GC.with_fixture(|fixture| {
+        mmtk_run_pending_handlers();
+        RECEIVED.lock().unwrap().clear();
+        mmtk_set_reference_queue_handler(Some(handler));
+        let mut plain_rtti = MockRtti::plain();
+        let mut weak_rtti = MockRtti::weak_reference(1);
+        let mut holder_rtti = MockRtti::holder();
+
+        let unreachable = fixture.alloc(plain_rtti.as_ptr(), &[1]);
+        mmtk_add_finalizer(unreachable);
+        let dead = fixture.alloc(plain_rtti.as_ptr(), &[2]);
+        let to_dead = fixture.alloc(weak_rtti.as_ptr(), &[dead.to_raw_address().as_usize()]);
+        // Weak references are discovered when they are reached through a field.
+        let holder = fixture.alloc(holder_rtti.as_ptr(), &[to_dead.to_raw_address().as_usize(), 0]);
+        #[cfg(feature = "mmtk_reference_processor")]
+        mmtk_add_weak_candidate(to_dead);
+        set_mock_module(0, holder.to_raw_address());
+        let finalizers_scheduled = MOCK_FINALIZERS_SCHEDULED.load(Ordering::SeqCst);
+
+        mmtk_handle_user_collection_request(fixture.thread.tls());
+
+        let to_dead = ObjectReference::from_raw_address(field_of(mock_module(0), 0));
+        assert!(VMReferenceGlue::get_referent(to_dead).is_null());
+        // The GC is over, but nothing ran on the way out of `block_for_gc`.
+        assert_eq!(MOCK_FINALIZERS_SCHEDULED.load(Ordering::SeqCst), finalizers_scheduled);
+        assert!(RECEIVED.lock().unwrap().is_empty());
+
+        mmtk_run_pending_handlers();
+        assert_eq!(MOCK_FINALIZERS_SCHEDULED.load(Ordering::SeqCst), finalizers_scheduled + 1);
+        assert_eq!(*RECEIVED.lock().unwrap(), vec![to_dead.to_raw_address().as_usize()]);
+        assert!(!MOCK_REFERENCE_HANDLER_ATTACHED.load(Ordering::SeqCst));
+        let finalized = mmtk_get_finalized_object();
+        assert_eq!(payload_of(finalized.to_raw_address()), 1);
+        assert!(mmtk_get_finalized_object().is_null());
+
+        // Without another GC, there is nothing left to run.
+        mmtk_run_pending_handlers();
+        assert_eq!(MOCK_FINALIZERS_SCHEDULED.load(Ordering::SeqCst), finalizers_scheduled + 1);
+
+        mmtk_set_reference_queue_handler(None);
+        set_mock_module(0, Address::ZERO);
+    });
+}
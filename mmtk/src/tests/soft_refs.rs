@@ -0,0 +1,125 @@
+use crate::soft_refs::*;
+
+#[test]
+pub fn retain_all_with_free_heap() {
+    let soft_refs = SoftReferences::new();
+    soft_refs.record_free_heap(50, 100);
+    assert_eq!(soft_refs.retention_epoch(10, false), 0);
+}
+
+#[test]
+pub fn clear_idle_under_pressure() {
+    let soft_refs = SoftReferences::new();
+    soft_refs.record_free_heap(DEFAULT_FREE_HEAP_PERCENT - 1, 100);
+    assert_eq!(soft_refs.retention_epoch(10, false), 10 - DEFAULT_MAX_IDLE_GCS);
+    // Early GCs do not underflow.
+    assert_eq!(soft_refs.retention_epoch(1, false), 0);
+}
+
+#[test]
+pub fn retain_the_last_epoch_in_emergency() {
+    let soft_refs = SoftReferences::new();
+    soft_refs.record_free_heap(100, 100);
+    assert_eq!(soft_refs.retention_epoch(10, true), 9);
+    assert_eq!(soft_refs.retention_epoch(0, true), 0);
+}
+
+#[test]
+pub fn custom_policy() {
+    let soft_refs = SoftReferences::new();
+    soft_refs.set_policy(50, 1);
+    soft_refs.record_free_heap(40, 100);
+    assert_eq!(soft_refs.retention_epoch(10, false), 9);
+    soft_refs.record_free_heap(60, 100);
+    assert_eq!(soft_refs.retention_epoch(10, false), 0);
+}
+
+#[cfg(not(feature = "mmtk_reference_processor"))]
+mod gc {
+    use mmtk::util::{Address, ObjectReference};
+
+    use crate::api::*;
+    use crate::reference_glue::referent_of;
+    use crate::soft_refs::*;
+    use crate::tests::fixtures::mock_vm::*;
+    use crate::tests::fixtures::MOCK_GC;
+
+    /// Soft references share the weak reference layout, followed by the timestamp field.
+    const TIMESTAMP_OFFSET: i32 = MOCK_WEAK_REF_FIELD_OFFSET + 1;
+
+    fn reference_at(object: Address, index: usize) -> ObjectReference {
+        ObjectReference::from_raw_address(field_of(object, index))
+    }
+
+    #[test]
+    pub fn recently_accessed_referents_survive_pressure() {
+        MOCK_GC.with_fixture(|fixture| {
+            mmtk_register_soft_ref_timestamp(TIMESTAMP_OFFSET);
+            // Always under pressure: only the soft references accessed since the last GC are retained.
+            mmtk_set_soft_ref_policy(101, 1);
+            let mut plain_rtti = MockRtti::plain();
+            let mut soft_rtti = MockRtti::weak_reference(2);
+            let mut holder_rtti = MockRtti::holder();
+
+            let recent_referent = fixture.alloc(plain_rtti.as_ptr(), &[1]);
+            let old_referent = fixture.alloc(plain_rtti.as_ptr(), &[2]);
+            let recent = fixture.alloc(soft_rtti.as_ptr(), &[recent_referent.to_raw_address().as_usize(), 0]);
+            let old = fixture.alloc(soft_rtti.as_ptr(), &[old_referent.to_raw_address().as_usize(), 0]);
+            mmtk_add_soft_candidate(recent);
+            mmtk_add_soft_candidate(old);
+            // Soft references are discovered when they are reached through a field.
+            let holder = fixture.alloc(holder_rtti.as_ptr(), &[recent.to_raw_address().as_usize(), old.to_raw_address().as_usize()]);
+            set_mock_module(0, holder.to_raw_address());
+
+            // Both were registered, i.e. accessed, since the last GC.
+            fixture.collect();
+            let holder = mock_module(0);
+            assert!(!referent_of(reference_at(holder, 0)).is_null());
+            assert!(!referent_of(reference_at(holder, 1)).is_null());
+
+            mmtk_soft_ref_accessed(reference_at(holder, 0));
+            fixture.collect();
+            let holder = mock_module(0);
+            let recent_referent = referent_of(reference_at(holder, 0));
+            assert!(!recent_referent.is_null());
+            assert_eq!(payload_of(recent_referent.to_raw_address()), 1);
+            assert!(referent_of(reference_at(holder, 1)).is_null());
+
+            set_mock_module(0, Address::ZERO);
+            mmtk_set_soft_ref_policy(DEFAULT_FREE_HEAP_PERCENT, DEFAULT_MAX_IDLE_GCS as usize);
+        });
+    }
+
+    #[test]
+    pub fn soft_references_reachable_through_retained_referents_are_retained() {
+        MOCK_GC.with_fixture(|fixture| {
+            mmtk_register_soft_ref_timestamp(TIMESTAMP_OFFSET);
+            // Never under pressure: every soft referent is retained.
+            mmtk_set_soft_ref_policy(0, 1);
+            let mut plain_rtti = MockRtti::plain();
+            let mut soft_rtti = MockRtti::weak_reference(2);
+            let mut holder_rtti = MockRtti::holder();
+
+            // outer -> holder -> inner -> referent, where only outer is a root, and the
+            // references from outer and inner are soft.
+            let referent = fixture.alloc(plain_rtti.as_ptr(), &[3]);
+            let inner = fixture.alloc(soft_rtti.as_ptr(), &[referent.to_raw_address().as_usize(), 0]);
+            let holder = fixture.alloc(holder_rtti.as_ptr(), &[inner.to_raw_address().as_usize(), 0]);
+            let outer = fixture.alloc(soft_rtti.as_ptr(), &[holder.to_raw_address().as_usize(), 0]);
+            mmtk_add_soft_candidate(inner);
+            mmtk_add_soft_candidate(outer);
+            set_mock_module(0, outer.to_raw_address());
+
+            fixture.collect();
+
+            let holder = referent_of(ObjectReference::from_raw_address(mock_module(0)));
+            assert!(!holder.is_null());
+            let referent = referent_of(reference_at(holder.to_raw_address(), 0));
+            assert!(!referent.is_null());
+            assert_eq!(payload_of(referent.to_raw_address()), 3);
+
+            set_mock_module(0, Address::ZERO);
+            mmtk_set_soft_ref_policy(DEFAULT_FREE_HEAP_PERCENT, DEFAULT_MAX_IDLE_GCS as usize);
+        });
+    }
+}
@@ -0,0 +1,103 @@
+use std::mem;
+
+use mmtk::util::Address;
+
+use crate::abi::*;
+use crate::api::*;
+use crate::tests::fixtures::mock_vm::*;
+use crate::tests::fixtures::MOCK_GC;
+
+const STACK_CHUNK_ID: i32 = 30;
+
+#[test]
+pub fn stack_chunk_layout() {
+    install_mock_upcalls();
+    mmtk_register_stack_chunk_ids(STACK_CHUNK_ID, STACK_CHUNK_ID);
+
+    let mut rtti = MockRtti::new(STACK_CHUNK_ID, 0, std::ptr::null_mut());
+    let header_words = mem::size_of::<StackChunk>() / mem::size_of::<word_t>();
+    let payload = [0x11usize, 0x22, 0x33];
+    let mut memory = vec![0usize; header_words + payload.len() + 1];
+    memory[0] = rtti.as_ptr() as usize;
+    memory[header_words - 1] = payload.len() * mem::size_of::<word_t>();
+    memory[header_words..header_words + payload.len()].copy_from_slice(&payload);
+
+    let object = unsafe { &*(memory.as_ptr() as *const Object) };
+    assert!(object.is_stack_chunk());
+    assert!(!object.is_array());
+
+    let chunk = unsafe { object.as_stack_chunk() };
+    assert_eq!(chunk.words(), &payload);
+    assert_eq!(object.size(), chunk.size_in_heap());
+    assert_eq!(object.size() % MOCK_ALLOCATION_ALIGNMENT, 0);
+    assert!(object.size() >= mem::size_of::<StackChunk>() + mem::size_of_val(&payload));
+}
+
+#[test]
+pub fn other_objects_are_not_stack_chunks() {
+    install_mock_upcalls();
+    mmtk_register_stack_chunk_ids(STACK_CHUNK_ID, STACK_CHUNK_ID);
+
+    let mut rtti = MockRtti::new(STACK_CHUNK_ID + 1, mem::size_of::<Object>(), std::ptr::null_mut());
+    let memory = vec![rtti.as_ptr() as usize, 0, 0, 0];
+    let object = unsafe { &*(memory.as_ptr() as *const Object) };
+    assert!(!object.is_stack_chunk());
+}
+
+fn chunk_words(chunk: Address) -> Vec<usize> {
+    unsafe { (*chunk.to_mut_ptr::<Object>()).as_stack_chunk() }.words().to_vec()
+}
+
+#[test]
+pub fn stack_chunks_keep_their_referents_alive() {
+    MOCK_GC.with_fixture(|fixture| {
+        mmtk_register_stack_chunk_ids(STACK_CHUNK_ID, STACK_CHUNK_ID);
+        let mut plain_rtti = MockRtti::plain();
+        let mut chunk_rtti = MockRtti::new(STACK_CHUNK_ID, 0, std::ptr::null_mut());
+
+        let referent = fixture.alloc(plain_rtti.as_ptr(), &[7]).to_raw_address();
+        let not_a_pointer = 0x1234_5677usize;
+        let words = [referent.as_usize(), not_a_pointer];
+        let chunk = fixture.alloc(chunk_rtti.as_ptr(), &[mem::size_of_val(&words), words[0], words[1]]);
+        // Only the chunk is a root: its referent is found when the chunk is traced.
+        set_mock_module(0, chunk.to_raw_address());
+
+        fixture.collect();
+
+        let chunk_after = mock_module(0);
+        let words_after = chunk_words(chunk_after);
+        assert!(mmtk_is_mmtk_object(chunk_after));
+        assert!(mmtk_is_mmtk_object(Address::from_usize(words_after[0])));
+        assert_eq!(payload_of(Address::from_usize(words_after[0])), 7);
+        assert_eq!(words_after[1], not_a_pointer);
+        // Pinned referents stay where they are, even when everything else is copied.
+        #[cfg(all(feature = "object_pinning", feature = "immix_stress_copying"))]
+        assert_eq!(words_after, words);
+
+        set_mock_module(0, Address::ZERO);
+    });
+}
+
+#[cfg(not(feature = "mmtk_reference_processor"))]
+#[test]
+pub fn dead_stack_chunks_do_not_keep_their_referents_alive() {
+    MOCK_GC.with_fixture(|fixture| {
+        mmtk_register_stack_chunk_ids(STACK_CHUNK_ID, STACK_CHUNK_ID);
+        let mut plain_rtti = MockRtti::plain();
+        let mut weak_rtti = MockRtti::weak_reference(1);
+        let mut chunk_rtti = MockRtti::new(STACK_CHUNK_ID, 0, std::ptr::null_mut());
+
+        let referent = fixture.alloc(plain_rtti.as_ptr(), &[7]).to_raw_address();
+        let words = [referent.as_usize()];
+        fixture.alloc(chunk_rtti.as_ptr(), &[mem::size_of_val(&words), words[0]]);
+        // The referent is only reachable from the dead chunk, and watched by a weak reference.
+        let weak_ref = fixture.alloc(weak_rtti.as_ptr(), &[referent.as_usize()]);
+        set_mock_module(0, weak_ref.to_raw_address());
+
+        fixture.collect();
+
+        assert!(field_of(mock_module(0), 0).is_zero());
+
+        set_mock_module(0, Address::ZERO);
+    });
+}
@@ -0,0 +1,27 @@
+use std::sync::atomic::Ordering;
+
+use crate::api::*;
+use crate::tests::fixtures::mock_vm::*;
+use crate::tests::fixtures::MOCK_GC;
+
+// MMTk scans the roots of each mutator passed to the visitor of `stop_all_mutators`, so the
+// number of times a stack was scanned is the number of times its mutator was visited.
+#[test]
+pub fn every_mutator_is_visited_once_per_gc() {
+    MOCK_GC.with_fixture(|fixture| {
+        let mut other = MockMutatorThread::new();
+        other.mutator = mmtk_bind_mutator(other.tls());
+        // Bound mutators stay registered for the rest of the process.
+        let other: &'static MockMutatorThread = Box::leak(other);
+
+        let threads = [&*fixture.thread, other];
+        let before: Vec<usize> = threads.iter().map(|t| t.stack_scans.load(Ordering::SeqCst)).collect();
+        fixture.collect();
+        let after: Vec<usize> = threads.iter().map(|t| t.stack_scans.load(Ordering::SeqCst)).collect();
+        assert_eq!(after, before.iter().map(|scans| scans + 1).collect::<Vec<_>>());
+
+        fixture.collect();
+        let again: Vec<usize> = threads.iter().map(|t| t.stack_scans.load(Ordering::SeqCst)).collect();
+        assert_eq!(again, after.iter().map(|scans| scans + 1).collect::<Vec<_>>());
+    });
+}
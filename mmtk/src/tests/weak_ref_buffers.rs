@@ -0,0 +1,123 @@
+use std::thread;
+
+use mmtk::util::{Address, ObjectReference};
+
+use crate::abi::Object;
+use crate::scanning::{nullify_weak_refs, WeakRefBuffer, WeakRefBuffers};
+use crate::tests::fixtures::mock_vm::*;
+
+const WORKERS: usize = 8;
+const WORD: usize = std::mem::size_of::<usize>();
+const OBJECT_WORDS: usize = HEADER_WORDS + 1;
+/// The referents are never dereferenced, so they are made up addresses from this one on.
+const REFERENTS_BASE: usize = 0x1000_0000;
+
+#[test]
+pub fn merge_worker_buffers() {
+    install_mock_upcalls();
+    const COUNT: usize = 10_000;
+    let mut rtti = MockRtti::new(MOCK_WEAK_REF_IDS_MIN, std::mem::size_of::<Object>() + 8, std::ptr::null_mut());
+    let mut heap = Vec::with_capacity(COUNT * OBJECT_WORDS);
+    for i in 0..COUNT {
+        heap.extend(mock_object(rtti.as_ptr(), &[REFERENTS_BASE + i * 16]));
+    }
+
+    // A registry of its own, so the GCs of other tests do not take these weak references.
+    let buffers: Vec<WeakRefBuffer> = (0..WORKERS).map(|_| WeakRefBuffer::new()).collect();
+    let registry = WeakRefBuffers::new();
+    let heap_range = heap.as_ptr() as usize..heap.as_ptr() as usize + heap.len() * WORD;
+    thread::scope(|scope| {
+        for (buffer, chunk) in buffers.iter().zip(heap.chunks(((COUNT + WORKERS - 1) / WORKERS) * OBJECT_WORDS)) {
+            registry.add(buffer);
+            let chunk = chunk.as_ptr() as usize..chunk.as_ptr() as usize + chunk.len() * WORD;
+            scope.spawn(move || {
+                for object in chunk.step_by(OBJECT_WORDS * WORD) {
+                    buffer.push(object as *mut Object);
+                }
+            });
+        }
+    });
+    assert_eq!(registry.len(), WORKERS);
+    let mut weak_refs = Vec::new();
+    registry.drain_into(&mut weak_refs);
+    assert_eq!(weak_refs.len(), COUNT);
+
+    // Nullify the referents of every other weak reference.
+    let cleared = nullify_weak_refs(weak_refs, |object| {
+        let address = object.to_raw_address().as_usize();
+        if heap_range.contains(&address) || (address - REFERENTS_BASE) % 32 == 0 {
+            Some(object)
+        } else {
+            None
+        }
+    });
+    assert_eq!(cleared.len(), COUNT / 2);
+    let first_cleared = ObjectReference::from_raw_address(unsafe { Address::from_usize(heap.as_ptr() as usize) } + OBJECT_WORDS * WORD);
+    assert!(cleared.contains(&first_cleared));
+
+    // The buffers are empty once merged.
+    let mut weak_refs = Vec::new();
+    registry.drain_into(&mut weak_refs);
+    assert!(weak_refs.is_empty());
+
+    // Ephemerons are buffered apart from the weak references.
+    buffers[0].push_ephemeron(heap.as_mut_ptr() as *mut Object);
+    registry.drain_into(&mut weak_refs);
+    assert!(weak_refs.is_empty());
+    let mut ephemerons = Vec::new();
+    registry.drain_ephemerons_into(&mut ephemerons);
+    assert_eq!(ephemerons.len(), 1);
+}
+
+/// Time a GC of a heap holding a million weak references, half of whose referents die.
+/// The heap must be large enough for them:
+/// `MOCK_GC_HEAP_MB=512 RUST_LOG=info cargo test --release bench_weak_ref_processing -- --ignored`.
+/// `BENCH_WEAK_REFS` sets another number of weak references. The time is logged.
+#[cfg(not(feature = "mmtk_reference_processor"))]
+#[test]
+#[ignore]
+pub fn bench_weak_ref_processing() {
+    use std::time::Instant;
+
+    use log::info;
+
+    use crate::reference_glue::referent_of;
+    use crate::tests::fixtures::MOCK_GC;
+
+    // Weak references with a strong `next` field after the referent, chained into a list.
+    static WEAK_REF_MAP: [i64; 3] = [MOCK_WEAK_REF_FIELD_OFFSET as i64, MOCK_WEAK_REF_FIELD_OFFSET as i64 + 1, crate::object_scanning::LAST_FIELD_OFFSET];
+    let count: usize = std::env::var("BENCH_WEAK_REFS").map_or(1_000_000, |count| count.parse().expect("BENCH_WEAK_REFS must be a number"));
+    MOCK_GC.with_fixture(|fixture| {
+        let mut plain_rtti = MockRtti::plain();
+        let mut weak_rtti = MockRtti::new(MOCK_WEAK_REF_IDS_MIN, std::mem::size_of::<Object>() + 16, WEAK_REF_MAP.as_ptr() as *mut i64);
+
+        // Every other weak reference refers to the next one in the list, which is alive.
+        let mut list = 0;
+        for i in 0..count {
+            let referent = if i % 2 == 0 {
+                fixture.alloc(plain_rtti.as_ptr(), &[i]).to_raw_address().as_usize()
+            } else {
+                list
+            };
+            list = fixture.alloc(weak_rtti.as_ptr(), &[referent, list]).to_raw_address().as_usize();
+        }
+        set_mock_module(0, unsafe { Address::from_usize(list) });
+
+        let start = Instant::now();
+        fixture.collect();
+        let gc_time = start.elapsed();
+
+        let mut cleared = 0;
+        let mut weak_ref = mock_module(0);
+        while !weak_ref.is_zero() {
+            if referent_of(ObjectReference::from_raw_address(weak_ref)).is_null() {
+                cleared += 1;
+            }
+            weak_ref = field_of(weak_ref, 1);
+        }
+        assert_eq!(cleared, (count + 1) / 2);
+        info!("GC of {} weak references: {:?}", count, gc_time);
+
+        set_mock_module(0, Address::ZERO);
+    });
+}
@@ -0,0 +1,77 @@
+use std::collections::HashMap;
+
+use mmtk::util::{Address, ObjectReference};
+
+use crate::abi::Object;
+use crate::scanning::{nullify_weak_refs, ObjectSendPtr};
+use crate::tests::fixtures::mock_vm::*;
+
+fn objref(addr: usize) -> ObjectReference {
+    ObjectReference::from_raw_address(unsafe { Address::from_usize(addr) })
+}
+
+fn object_of(memory: &mut [usize]) -> *mut Object {
+    memory.as_mut_ptr() as *mut Object
+}
+
+fn referent_of(memory: &[usize]) -> usize {
+    memory[HEADER_WORDS + MOCK_WEAK_REF_FIELD_OFFSET as usize]
+}
+
+#[test]
+pub fn nullify_mock_graph() {
+    install_mock_upcalls();
+    let mut rtti = MockRtti::new(MOCK_WEAK_REF_IDS_MIN, std::mem::size_of::<Object>() + 8, std::ptr::null_mut());
+
+    // The referents are never dereferenced, so they do not need to be real objects.
+    const LIVE: usize = 0x10000;
+    const DEAD: usize = 0x20000;
+    const MOVED_FROM: usize = 0x30000;
+    const MOVED_TO: usize = 0x38000;
+
+    let mut to_live = mock_object(rtti.as_ptr(), &[LIVE]);
+    let mut to_dead = mock_object(rtti.as_ptr(), &[DEAD]);
+    let mut to_moved = mock_object(rtti.as_ptr(), &[MOVED_FROM]);
+    let mut to_null = mock_object(rtti.as_ptr(), &[0]);
+    // A weak reference that is itself moved: it was pushed at its old address, and the
+    // referent must be read from and updated in its new copy.
+    let mut copied_from = mock_object(rtti.as_ptr(), &[DEAD]);
+    let mut copied_to = copied_from.clone();
+
+    let mut liveness: HashMap<ObjectReference, ObjectReference> = HashMap::new();
+    for memory in [&mut to_live, &mut to_dead, &mut to_moved, &mut to_null, &mut copied_to] {
+        let object = objref(object_of(memory) as usize);
+        liveness.insert(object, object);
+    }
+    liveness.insert(objref(object_of(&mut copied_from) as usize), objref(object_of(&mut copied_to) as usize));
+    liveness.insert(objref(LIVE), objref(LIVE));
+    liveness.insert(objref(MOVED_FROM), objref(MOVED_TO));
+
+    let stack = vec![
+        ObjectSendPtr(object_of(&mut to_live)),
+        ObjectSendPtr(object_of(&mut to_dead)),
+        ObjectSendPtr(object_of(&mut to_moved)),
+        ObjectSendPtr(object_of(&mut to_null)),
+        ObjectSendPtr(object_of(&mut copied_from)),
+        // Duplicates, including one through the new address of a moved weak reference.
+        ObjectSendPtr(object_of(&mut to_dead)),
+        ObjectSendPtr(object_of(&mut to_moved)),
+        ObjectSendPtr(object_of(&mut copied_to)),
+    ];
+
+    let cleared = nullify_weak_refs(stack, |object| liveness.get(&object).copied());
+
+    assert_eq!(referent_of(&to_live), LIVE);
+    assert_eq!(referent_of(&to_dead), 0);
+    assert_eq!(referent_of(&to_moved), MOVED_TO);
+    assert_eq!(referent_of(&to_null), 0);
+    assert_eq!(referent_of(&copied_to), 0);
+    // The old copy is left untouched.
+    assert_eq!(referent_of(&copied_from), DEAD);
+
+    // Each cleared weak reference is reported once, at its new address.
+    assert_eq!(cleared, vec![
+        objref(object_of(&mut to_dead) as usize),
+        objref(object_of(&mut copied_to) as usize),
+    ]);
+}
@@ -28,6 +28,11 @@ typedef struct {
     void* data;
 } MutatorClosure;
 
+typedef struct {
+    void (*func)(uintptr_t** start, uintptr_t** end, void* data);
+    void* data;
+} RangesClosure;
+
 typedef struct {
     NewBuffer (*func)(void** buf, size_t size, size_t capa, void* data);
     void* data;
@@ -42,6 +47,7 @@ void invoke_MutatorClosure(MutatorClosure* closure, MMTk_Mutator mutator);
 NewBuffer invoke_EdgesClosure(EdgesClosure* closure, void** buf, size_t size, size_t capa);
 NewBuffer invoke_NodesClosure(NodesClosure* closure, void** buf, size_t size, size_t capa);
 extern void invoke_mutator_closure(MutatorClosure* closure, MMTk_Mutator mutator);
+extern void invoke_ranges_closure(RangesClosure* closure, uintptr_t** start, uintptr_t** end);
 extern void visit_edge(void* edge_visitor, void* edge);
 
 typedef struct {
@@ -65,7 +71,6 @@ typedef struct {
     void* ptr;
 } SendCtxPtr;
 
-typedef struct MutatorThreadNode MutatorThreadNode;
 typedef uintptr_t word_t;
 
 typedef struct {
@@ -74,6 +79,8 @@ typedef struct {
     void (*block_for_gc)(void *tls);
     void (*out_of_memory)(void* tls, MMTkAllocationError err_kind);
     void (*schedule_finalizer)();
+    void (*attach_reference_handler_thread)();
+    void (*detach_reference_handler_thread)();
 
     int (*get_object_array_id)();
     int (*get_weak_ref_ids_min)();
@@ -85,9 +92,9 @@ typedef struct {
 
     StackRange (*mmtk_get_stack_range)(void* thread);
     RegsRange (*mmtk_get_regs_range)(void* thread);
+    void (*mmtk_get_extra_ranges)(void* thread, RangesClosure closure);
     word_t** (*mmtk_get_modules)();
     int (*mmkt_get_modules_size)();
-    MutatorThreadNode* (*mmtk_get_mutator_threads)();
 
     void (*scan_roots_in_all_mutator_threads)(NodesClosure closure);
     void (*scan_roots_in_mutator_thread)(NodesClosure closure, void* tls);
@@ -97,15 +104,9 @@ typedef struct {
     void (*weak_ref_stack_nullify)();
     void (*weak_ref_stack_call_handlers)();
 
-    void (*get_mutators)(MutatorClosure closure);
-    bool (*is_mutator)(void* tls);
-    size_t (*number_of_mutators)();
-    void* (*get_mmtk_mutator)(void* tls);
-
     void (*init_gc_worker_thread)(MMTk_GCThreadTLS *gc_worker_tls, SendCtxPtr ctx_ptr);
     MMTk_GCThreadTLS* (*get_gc_thread_tls)();
     void (*init_synchronizer_thread)();
-    size_t (*get_mutatorContext_offset)();
 } ScalaNative_Upcalls;
 
 extern const uintptr_t GLOBAL_SIDE_METADATA_BASE_ADDRESS;
@@ -187,9 +188,85 @@ extern void release_buffer(void** buf, size_t size, size_t capa);
 extern void* mmtk_starting_heap_address();
 extern void* mmtk_last_heap_address();
 
+// Register [start, end) as a range to be scanned conservatively for roots
+extern void mmtk_add_roots(void* start, void* end);
+// Unregister all root ranges contained in [start, end)
+extern void mmtk_remove_roots(void* start, void* end);
+
+// Global handles: slots that keep an object alive and are updated when it moves
+typedef void** MMTk_Handle;
+extern MMTk_Handle mmtk_handle_new(void* obj);
+extern void* mmtk_handle_get(MMTk_Handle handle);
+extern void mmtk_handle_free(MMTk_Handle handle);
+
+// Stack chunks of delimited continuations: objects with an rtti id in [min, max] whose
+// payload is scanned conservatively whenever the chunk is traced. With object_pinning,
+// the objects its words refer to are pinned for the GC; otherwise they may be moved and
+// the words updated
+extern void mmtk_register_stack_chunk_ids(int min, int max);
+extern void mmtk_set_pin_stack_chunks(bool pin);
+
+// Ephemerons: objects with an rtti id in [min, max] whose value, at field index
+// value_offset, is only kept alive while their key, at field index key_offset, is reachable
+extern void mmtk_register_ephemerons(int min, int max, int key_offset, int value_offset);
+
 extern void mmtk_append_pinned_objects(uintptr_t* const *data, size_t len);
 extern bool mmtk_pin_object(uintptr_t* addr);
+extern bool mmtk_unpin_object(uintptr_t* addr);
+extern bool mmtk_is_pinned(uintptr_t* addr);
+
+// Pin scopes around native calls: objects pinned in a scope are unpinned when it exits.
+// Objects are pinned in managed state, before mmtk_enter_native.
+extern void mmtk_pin_scope_enter(void* mutator);
+extern bool mmtk_pin_in_scope(void* mutator, uintptr_t* addr);
+extern void mmtk_pin_scope_exit(void* mutator);
+// Also registers fork handlers. The GC threads do not survive fork(), so collection is
+// disabled in a forked child: its heap grows until it execs or exits.
 extern void scalanative_gc_init(ScalaNative_Upcalls *calls);
+// Waits for a collection in progress, disables collection and stops the synchronizer and
+// reference handler threads. This is not a shutdown: the GC controller and workers of
+// mmtk-core 0.21 cannot be stopped, and the heap stays mapped.
+extern void mmtk_stop_collection();
+// Single-threaded builds only: runs the reference handlers and schedules the finalizers
+// after a GC. Call it at a safe point, e.g. once an allocation returns. The GC does not run
+// on the mutator thread, which waits for the GC threads of mmtk-core.
+extern void mmtk_run_pending_handlers();
+
+// The binding keeps the list of mutators. Threads started by the runtime are registered by
+// mmtk_bind_mutator. Foreign threads calling into Scala attach themselves; attaching twice
+// returns the same mutator. mmtk_detach_current_thread returns false if the thread was not attached.
+typedef struct {
+    void* low;
+    void* high;
+} MMTk_StackBounds;
+extern MMTk_Mutator mmtk_attach_current_thread(void* tls);
+extern bool mmtk_detach_current_thread();
+extern bool mmtk_get_mutator_stack_bounds(MMTk_Mutator mutator, MMTk_StackBounds* bounds);
+extern void mmtk_set_safepoint_timeout(uint64_t timeout_ms);
+
+// Each mutator in managed state calls mmtk_safepoint_acknowledge when it stops. Once
+// stop_all_mutators returned, a collection waits for these calls, but not for the threads in
+// native state. Bucket i > 0 of the histogram counts the stops that took [2^(i-1), 2^i) us.
+typedef struct {
+    uint64_t count;
+    uint64_t total_ns;
+    uint64_t max_ns;
+    uint64_t last_ns;
+    uint64_t last_pause_ns;
+} MMTk_SafepointStats;
+extern void mmtk_safepoint_acknowledge(void* tls);
+extern void mmtk_get_safepoint_stats(MMTk_SafepointStats* stats);
+extern size_t mmtk_get_safepoint_histogram(uint64_t* buckets, size_t len);
+
+// GC-safe regions around blocking native calls. The mutator saves its stack range before
+// mmtk_enter_native; mmtk_exit_native blocks while a collection is in progress.
+extern void mmtk_enter_native(MMTk_Mutator mutator);
+extern void mmtk_exit_native(MMTk_Mutator mutator);
+
+// GC thread placement, before initialize_collection. The number of workers is read from
+// GC_NPROCS by mmtk_init. The CPU list is written as for `taskset -c`, e.g. "0-3,6".
+extern bool mmtk_set_gc_thread_affinity(const char* cpus);
+extern void mmtk_set_gc_thread_policy(int policy, int priority);
 extern void mmtk_init_binding(const ScalaNative_Upcalls *upcalls);
 
 extern size_t get_immix_bump_ptr_offset();
@@ -202,6 +279,23 @@ extern bool mmtk_is_live_object(void* ref);
 extern bool mmtk_is_reachable(void* ref);
 
 extern void mmtk_weak_ref_stack_set_handler(void* handler);
+// Receive the references cleared by each GC, after the mutators resume
+extern void mmtk_set_reference_queue_handler(void (*handler)(void** references, size_t len));
+
+// Register reference objects with MMTk's reference processor (with the
+// mmtk_reference_processor feature). Soft references are always registered: without
+// that feature, mmtk_add_soft_candidate registers them with the binding's own soft
+// reference policy instead, and requires mmtk_register_soft_ref_timestamp to have been
+// called first; otherwise the soft reference is only treated as a weak reference.
+extern void mmtk_add_weak_candidate(void* ref);
+extern void mmtk_add_soft_candidate(void* ref);
+extern void mmtk_add_phantom_candidate(void* ref);
+
+// Soft references: register the index of the 64-bit field holding the last access, record
+// an access from SoftReference.get, and configure when they are cleared
+extern void mmtk_register_soft_ref_timestamp(int offset);
+extern void mmtk_soft_ref_accessed(void* ref);
+extern void mmtk_set_soft_ref_policy(size_t free_heap_percent, size_t max_idle_gcs);
 
 extern void mmtk_harness_begin(void* tls);
 